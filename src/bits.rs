@@ -10,14 +10,23 @@ macro_rules! divrem {
 
 mod mask;
 mod rrr;
+pub(crate) mod simd;
 
 pub mod pop_vec;
 pub use pop_vec::Pop;
 
 pub mod bit_array;
 pub mod bit_vec;
+pub mod bsi;
+pub mod ewah;
+pub mod expr;
+pub mod hybrid;
+pub mod index;
 pub mod map;
-pub use {bit_array::BitArray, bit_vec::BitVec};
+pub mod persist;
+pub mod run_vec;
+pub mod veb;
+pub use {bit_array::BitArray, bit_vec::BitVec, hybrid::Hybrid, run_vec::RunVec, veb::VebSet};
 
 pub use {
     mask::{and, and_not, or, xor},
@@ -25,9 +34,9 @@ pub use {
 };
 
 pub use {
-    mask::{And, AndNot, Or, Xor},
+    mask::{And, AndNot, Not, Or, Within, Xor},
     mask::{Difference, Intersection, SymmetricDifference, Union},
-    mask::{Fold, Mask},
+    mask::{Fold, FromMask, Mask},
 };
 
 use std::ops::{Bound, RangeBounds};
@@ -144,7 +153,7 @@ impl<T: FixedBits> Bits for [T] {
     /// ```
     #[inline]
     fn count1(&self) -> usize {
-        self.iter().fold(0, |acc, block| acc + block.count1())
+        T::count1_slice(self)
     }
 
     /// ```
@@ -155,7 +164,7 @@ impl<T: FixedBits> Bits for [T] {
     /// ```
     #[inline]
     fn count0(&self) -> usize {
-        self.iter().fold(0, |acc, block| acc + block.count0())
+        self.size() - self.count1()
     }
 
     /// ```
@@ -268,9 +277,7 @@ impl<T: Word, A: ?Sized + AsRef<[T]>> Intersection<A> for [T] {
     fn intersection(&mut self, slice: &A) {
         let slice = slice.as_ref();
         assert_eq!(self.len(), slice.len());
-        for (v1, &v2) in self.iter_mut().zip(slice) {
-            *v1 &= v2;
-        }
+        simd::and(simd::bytes_mut(self), simd::bytes(slice));
     }
 }
 
@@ -278,9 +285,7 @@ impl<T: Word, A: ?Sized + AsRef<[T]>> Union<A> for [T] {
     fn union(&mut self, slice: &A) {
         let slice = slice.as_ref();
         assert_eq!(self.len(), slice.len());
-        for (v1, &v2) in self.iter_mut().zip(slice) {
-            *v1 |= v2;
-        }
+        simd::or(simd::bytes_mut(self), simd::bytes(slice));
     }
 }
 
@@ -288,9 +293,7 @@ impl<T: Word, A: ?Sized + AsRef<[T]>> Difference<A> for [T] {
     fn difference(&mut self, slice: &A) {
         let slice = slice.as_ref();
         assert_eq!(self.len(), slice.len());
-        for (v1, &v2) in self.iter_mut().zip(slice) {
-            *v1 &= !v2;
-        }
+        simd::and_not(simd::bytes_mut(self), simd::bytes(slice));
     }
 }
 
@@ -298,9 +301,7 @@ impl<T: Word, A: ?Sized + AsRef<[T]>> SymmetricDifference<A> for [T] {
     fn symmetric_difference(&mut self, slice: &A) {
         let slice = slice.as_ref();
         assert_eq!(self.len(), slice.len());
-        for (v1, &v2) in self.iter_mut().zip(slice) {
-            *v1 ^= v2;
-        }
+        simd::xor(simd::bytes_mut(self), simd::bytes(slice));
     }
 }
 
@@ -435,6 +436,28 @@ where
     }
 }
 
+impl<'a, T: Word> FromMask<'a, [T]> for Vec<T> {
+    fn from_mask<M: Mask<'a, Block = [T]>>(mask: M) -> Self {
+        let steps = mask.into_steps();
+        let mut vec = Vec::new();
+        for (index, block) in steps {
+            let len = block.len();
+            let (i, j) = (index * len, index * len + len);
+            if vec.len() < j {
+                vec.resize(j, T::NONE);
+            }
+            vec[i..j].copy_from_slice(&block);
+        }
+        vec
+    }
+
+    fn from_mask_in<M: Mask<'a, Block = [T]>>(mask: M, len: usize) -> Self {
+        let mut vec = Vec::from_mask(mask.within(..len));
+        vec.resize(blocks_by(len, T::BITS), T::NONE);
+        vec
+    }
+}
+
 mod slice {
     use super::*;
 
@@ -1,9 +1,17 @@
 #![allow(missing_docs)]
 
-use std::ops::RangeBounds;
+use std::{
+    io::{self, Read, Write},
+    mem,
+    ops::RangeBounds,
+};
 
 use crate::{
-    bits::Words,
+    bits::{
+        blocks_by,
+        persist::{self, Persist},
+        Difference, FromMask, Intersection, Mask, SymmetricDifference, Union, Words,
+    },
     num::{self, cast, Int, Word},
     ops::*,
 };
@@ -105,12 +113,102 @@ impl<T: Words> From<Vec<Option<Box<T>>>> for BitArray<Option<Box<T>>> {
     }
 }
 
+impl<'a, T: Word> FromMask<'a, [T]> for BitArray<T> {
+    fn from_mask<M: Mask<'a, Block = [T]>>(mask: M) -> Self {
+        BitArray::from(Vec::from_mask(mask))
+    }
+
+    fn from_mask_in<M: Mask<'a, Block = [T]>>(mask: M, len: usize) -> Self {
+        BitArray::from(Vec::from_mask_in(mask, len))
+    }
+}
+
+impl<T: Word> Persist for BitArray<T> {
+    fn tag() -> String {
+        format!("BitArray<u{}>", T::BITS)
+    }
+
+    /// Writes `ones`, words, and rank/select samples.
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        persist::write_u64(w, self.ones)?;
+        persist::write_words(w, &self.data)?;
+        persist::write_words(w, &self.sum_samples.l0s)?;
+        let l1l2s = self
+            .sum_samples
+            .l1l2s
+            .iter()
+            .map(|l| l.0)
+            .collect::<Vec<_>>();
+        persist::write_words(w, &l1l2s)?;
+        persist::write_usize(w, self.idx_samples.idxs.len())?;
+        for idxs in &self.idx_samples.idxs {
+            persist::write_words(w, idxs)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let ones = persist::read_u64(r)?;
+        let data = persist::read_words::<_, T>(r)?;
+        let l0s = persist::read_words(r)?;
+        let l1l2s: Vec<L1L2> = persist::read_words(r)?.into_iter().map(L1L2).collect();
+        let mut idxs = Vec::new();
+        for _ in 0..persist::read_usize(r)? {
+            idxs.push(persist::read_words(r)?);
+        }
+
+        // samples are compared with the ones counted again, so that rank/select never
+        // return wrong answers from corrupted samples.
+        let sum_samples = SumSamples { l0s, l1l2s };
+        let idx_samples = IdxSamples { idxs };
+        let counted = {
+            let slice = data.as_slice();
+            samples(slice.size(), words(slice, SUPER_BLOCK))
+        };
+        if (ones, sum_samples, idx_samples) != counted {
+            return Err(persist::invalid("samples don't match the bits"));
+        }
+        let (ones, sum_samples, idx_samples) = counted;
+        Ok(BitArray {
+            ones,
+            data,
+            sum_samples,
+            idx_samples,
+        })
+    }
+}
+
+impl<T> AsRef<[T]> for BitArray<T> {
+    #[inline]
+    fn as_ref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+macro_rules! implSetOps {
+    ( $( ($Trait:ident, $method:ident) ),* ) => ($(
+        impl<T: Word, A: ?Sized + AsRef<[T]>> $Trait<A> for BitArray<T> {
+            /// Performs the operation on the bits, and rebuilds `BitArray` with new samples.
+            fn $method(&mut self, that: &A) {
+                let mut data = mem::take(&mut self.data);
+                data.$method(that.as_ref());
+                *self = BitArray::from(data);
+            }
+        }
+    )*)
+}
+implSetOps!(
+    (Intersection, intersection),
+    (Union, union),
+    (Difference, difference),
+    (SymmetricDifference, symmetric_difference)
+);
+
 fn samples<'a, T, I>(size: usize, supers: I) -> (u64, SumSamples, IdxSamples)
 where
     T: Word,
     I: Iterator<Item = Option<&'a [T]>>,
 {
-    use crate::bits::blocks_by;
     let mut l0s = vec![0; blocks_by(size, UPPER_BLOCK)];
     let mut l1l2s = vec![L1L2(0); blocks_by(size, SUPER_BLOCK)];
 
@@ -0,0 +1,280 @@
+//! `bsi` defines a bit-sliced index for integer columns.
+
+use std::{
+    borrow::Cow,
+    iter::{FromIterator, Peekable},
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::{
+    bits::{Mask, Words},
+    num::{Int, Word},
+    ops::*,
+    BitMap,
+};
+
+/// `Bsi` is a bit-sliced index, that keeps a `BitMap` for each bit of values.
+///
+/// Comparisons follow O'Neil and Quass, "Improved Query Performance with Variant Indexes".
+/// Each result is a `BitMap` of rows, and `&BitMap` is a `Mask` to compose with other filters.
+///
+/// ```
+/// use compacts::{bits::{bsi::Bsi, Mask}, ops::Bits};
+/// let bsi = vec![Some(3u32), Some(10), None, Some(7), Some(3)]
+///     .into_iter()
+///     .collect::<Bsi<u32, [u64; 1024]>>();
+///
+/// assert_eq!(bsi.get(1), Some(10));
+/// assert_eq!(bsi.get(2), None);
+/// assert_eq!(bsi.lt(7).count1(), 2);
+/// assert_eq!(bsi.range(3..10).count1(), 3);
+/// assert_eq!(bsi.eq(3).select1(1), Some(4));
+/// assert_eq!(bsi.ne(3).count1(), 2);
+///
+/// assert_eq!(bsi.sum(bsi.rows()), 23);
+/// assert_eq!(bsi.sum(&bsi.gt(3)), 17);
+/// let top = bsi.top_k(2, bsi.rows());
+/// assert!(top.bit(1) && top.bit(3) && top.count1() == 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bsi<V: Word, T: Words> {
+    slices: Vec<BitMap<T>>, // slices[i] has rows whose i-th bit is enabled
+    rows: BitMap<T>,        // rows that have a value
+    len: usize,
+    _value: PhantomData<V>,
+}
+
+impl<V: Word, T: Words> Bsi<V, T> {
+    /// Returns the number of rows, including rows that have no value.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the index has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns rows that have a value.
+    pub fn rows(&self) -> &BitMap<T> {
+        &self.rows
+    }
+
+    /// Returns the value of `row`.
+    pub fn get(&self, row: usize) -> Option<V> {
+        if row < self.len && self.rows.bit(row) {
+            let mut value = V::_0;
+            for (i, slice) in self.slices.iter().enumerate() {
+                if slice.bit(row) {
+                    value |= V::_1 << i;
+                }
+            }
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns rows whose value is equal to `c`.
+    pub fn eq(&self, c: V) -> BitMap<T> {
+        self.compare(c, |_, _, eq| eq)
+    }
+
+    /// Returns rows whose value is not equal to `c`.
+    pub fn ne(&self, c: V) -> BitMap<T> {
+        self.compare(c, |rows, _, eq| rows & !eq)
+    }
+
+    /// Returns rows whose value is less than `c`.
+    pub fn lt(&self, c: V) -> BitMap<T> {
+        self.compare(c, |_, lt, _| lt)
+    }
+
+    /// Returns rows whose value is less than or equal to `c`.
+    pub fn le(&self, c: V) -> BitMap<T> {
+        self.compare(c, |_, lt, eq| lt | eq)
+    }
+
+    /// Returns rows whose value is greater than `c`.
+    pub fn gt(&self, c: V) -> BitMap<T> {
+        self.compare(c, |rows, lt, eq| rows & !(lt | eq))
+    }
+
+    /// Returns rows whose value is greater than or equal to `c`.
+    pub fn ge(&self, c: V) -> BitMap<T> {
+        self.compare(c, |rows, lt, _| rows & !lt)
+    }
+
+    /// Returns rows whose value is in `range`.
+    pub fn range<R: RangeBounds<V>>(&self, range: R) -> BitMap<T> {
+        let lower = match range.start_bound() {
+            Bound::Included(&a) => Some(self.ge(a)),
+            Bound::Excluded(&a) => Some(self.gt(a)),
+            Bound::Unbounded => None,
+        };
+        let upper = match range.end_bound() {
+            Bound::Included(&b) => Some(self.le(b)),
+            Bound::Excluded(&b) => Some(self.lt(b)),
+            Bound::Unbounded => None,
+        };
+        match (lower, upper) {
+            (Some(lower), Some(upper)) => lower.and(&upper).materialize_in(self.len),
+            (Some(found), None) | (None, Some(found)) => found,
+            (None, None) => self.rows.clone(),
+        }
+    }
+
+    /// Computes `(lt, eq)` by scanning slices from the most significant bit,
+    /// a word at a time over borrowed blocks, and returns rows that `f(rows, lt, eq)` selects.
+    fn compare<F>(&self, c: V, f: F) -> BitMap<T>
+    where
+        F: Fn(T::Word, T::Word, T::Word) -> T::Word,
+    {
+        let mut slices = self.cursors();
+        let blocks = self.rows.into_steps().map(|(index, rows)| {
+            let slices = slices
+                .iter_mut()
+                .map(|s| seek(s, index))
+                .collect::<Vec<_>>();
+            let words = rows.iter().enumerate().map(|(w, &rows)| {
+                let mut lt = T::Word::NONE;
+                let mut eq = rows;
+                for (i, slice) in slices.iter().enumerate().rev() {
+                    let s = word(*slice, w);
+                    if c.bit(i) {
+                        lt |= eq & !s;
+                        eq &= s;
+                    } else {
+                        eq &= !s;
+                    }
+                }
+                f(rows, lt, eq)
+            });
+            (index, Cow::Owned(words.collect()))
+        });
+        blocks.materialize_in(self.len)
+    }
+
+    /// Sums values of `found` rows.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sum overflows `u128`.
+    pub fn sum<'a, M>(&'a self, found: M) -> u128
+    where
+        M: Mask<'a, Block = [T::Word]>,
+    {
+        // slices are subsets of rows, `found` needs not to be intersected with rows.
+        let mut slices = self.cursors();
+        let mut counts = vec![0usize; slices.len()];
+        for (index, found) in found.into_steps() {
+            for (count, slice) in counts.iter_mut().zip(&mut slices) {
+                if let Some(slice) = seek(slice, index) {
+                    let words = found.iter().zip(slice.iter());
+                    *count += words.map(|(&x, &y)| (x & y).count1()).sum::<usize>();
+                }
+            }
+        }
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count1)| (count1 as u128).checked_mul(1 << i).expect("overflow"))
+            .fold(0, |acc: u128, n| acc.checked_add(n).expect("overflow"))
+    }
+
+    /// Returns `k` rows that have the largest values in `found` rows.
+    ///
+    /// Ties at the boundary are broken by taking smaller rows.
+    /// If `found` has less than `k` rows, returns all of them.
+    pub fn top_k<'a, M>(&'a self, k: usize, found: M) -> BitMap<T>
+    where
+        M: Mask<'a, Block = [T::Word]>,
+    {
+        let mut gt = BitMap::<T>::none(self.len);
+        let mut eq = found.and(&self.rows).materialize::<BitMap<T>>();
+        for slice in self.slices.iter().rev() {
+            // counts candidates without building them, and builds only the chosen one.
+            let n = gt.or(eq.and(slice)).count_ones();
+            if n > k {
+                eq = eq.and(slice).materialize();
+            } else if n < k {
+                gt = gt.or(eq.and(slice)).materialize_in(self.len);
+                eq = eq.and_not(slice).materialize();
+            } else {
+                gt = gt.or(eq.and(slice)).materialize_in(self.len);
+                eq = BitMap::none(self.len);
+                break;
+            }
+        }
+
+        let rest = k - std::cmp::min(k, gt.count1());
+        match eq.select1(rest) {
+            Some(end) => gt.or(eq.within(..end)).materialize_in(self.len),
+            None => gt.or(&eq).materialize_in(self.len),
+        }
+    }
+
+    /// Cursors over blocks of each slice.
+    fn cursors(&self) -> Vec<Cursor<'_, T>> {
+        self.slices
+            .iter()
+            .map(|s| s.into_steps().peekable())
+            .collect()
+    }
+}
+
+type Cursor<'a, T> = Peekable<<&'a BitMap<T> as Mask<'a>>::Steps>;
+
+/// Advances `cursor` to `index`, and returns the block at `index` if it exists.
+fn seek<'b, 'a: 'b, T: Words>(
+    cursor: &'b mut Cursor<'a, T>,
+    index: usize,
+) -> Option<&'b [T::Word]> {
+    while matches!(cursor.peek(), Some(&(i, _)) if i < index) {
+        cursor.next();
+    }
+    match cursor.peek() {
+        Some((i, block)) if *i == index => Some(block),
+        _ => None,
+    }
+}
+
+/// The `w`-th word of `block`, or zero if `block` doesn't exist.
+fn word<W: Word>(block: Option<&[W]>, w: usize) -> W {
+    block.and_then(|b| b.get(w).cloned()).unwrap_or(W::NONE)
+}
+
+impl<V: Word, T: Words> FromIterator<Option<V>> for Bsi<V, T> {
+    /// Builds an index, a row is the position of a value, and `None` is a row without a value.
+    fn from_iter<I: IntoIterator<Item = Option<V>>>(iterable: I) -> Self {
+        let values = iterable.into_iter().collect::<Vec<_>>();
+        let len = values.len();
+
+        let mut slices = vec![BitMap::none(len); <V as Int>::BITS];
+        let mut rows = BitMap::none(len);
+        for (row, value) in values.into_iter().enumerate() {
+            if let Some(value) = value {
+                rows.put1(row);
+                for (i, slice) in slices.iter_mut().enumerate() {
+                    if value.bit(i) {
+                        slice.put1(row);
+                    }
+                }
+            }
+        }
+        Bsi {
+            slices,
+            rows,
+            len,
+            _value: PhantomData,
+        }
+    }
+}
+
+impl<V: Word, T: Words> FromIterator<V> for Bsi<V, T> {
+    /// Builds an index, a row is the position of a value.
+    fn from_iter<I: IntoIterator<Item = V>>(iterable: I) -> Self {
+        iterable.into_iter().map(Some).collect()
+    }
+}
@@ -0,0 +1,538 @@
+//! `ewah` defines an Enhanced Word-Aligned Hybrid compressed bitmap.
+//!
+//! A buffer is a sequence of markers, each marker is followed by literal words.
+//! A marker has three fields from the lowest bit.
+//!
+//! ```text
+//! | run bit (1) | run length (RUN_BITS) | literal count (LIT_BITS) |
+//! ```
+//!
+//! A marker represents `run length` words of `run bit`, followed by `literal count` words.
+//! Both fields have at most 32 and 31 bits, i.e. half of `W::BITS`.
+
+use std::{borrow::Cow, cmp, iter::FromIterator, ops::RangeBounds};
+
+use crate::{
+    bits::{blocks_by, to_exclusive, FromMask, Mask, Words},
+    num::{self, Int, Word},
+    ops::*,
+};
+
+/// `Ewah` is a run-length compressed bitmap, that is aligned by `W`.
+///
+/// Bitwise operations are performed in the compressed domain,
+/// a run of words is processed at once, without decompressing it.
+///
+/// ```
+/// use compacts::{bits::{ewah::Ewah, Fold, Mask}, ops::Bits, BitMap};
+/// let a = (0..100000).chain(500000..600000).collect::<Ewah<u64>>();
+/// let b = (50000..550000).collect::<Ewah<u64>>();
+/// assert_eq!(a.size(), 600000);
+/// assert_eq!(a.count1(), 200000);
+///
+/// let and = a.and(&b);
+/// assert_eq!(and.count1(), 100000);
+/// assert_eq!(and.select1(0), Some(50000));
+/// assert_eq!(a.or(&b).count1(), 600000);
+/// assert_eq!(a.xor(&b).count1(), 500000);
+/// assert_eq!(a.and_not(&b).rank1(..), 100000);
+///
+/// // steps interoperate with other containers.
+/// let map = a.steps::<[u64; 1024]>().and(b.steps::<[u64; 1024]>());
+/// assert_eq!(map.materialize::<BitMap<[u64; 1024]>>().count1(), 100000);
+/// let ewah = Fold::or(vec![a.steps::<[u64; 1024]>(), b.steps()]).materialize::<Ewah<u64>>();
+/// assert_eq!(ewah.count1(), 600000);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ewah<W: Word> {
+    buf: Vec<W>,
+    marker: usize, // index of the last marker in `buf`
+    words: usize,  // the number of words encoded
+    len: usize,    // the number of bits
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Marker {
+    bit: bool,
+    run: usize,
+    lits: usize,
+}
+
+impl Marker {
+    fn run_bits<W: Word>() -> usize {
+        cmp::min(W::BITS / 2, 32)
+    }
+    fn lit_bits<W: Word>() -> usize {
+        cmp::min(W::BITS - 1 - Self::run_bits::<W>(), 31)
+    }
+    fn max_run<W: Word>() -> usize {
+        num::mask1::<usize>(Self::run_bits::<W>())
+    }
+    fn max_lits<W: Word>() -> usize {
+        num::mask1::<usize>(Self::lit_bits::<W>())
+    }
+
+    fn decode<W: Word>(word: W) -> Self {
+        Marker {
+            bit: word.bit(0),
+            run: word.getn::<usize>(1, Self::run_bits::<W>()),
+            lits: word.getn::<usize>(1 + Self::run_bits::<W>(), Self::lit_bits::<W>()),
+        }
+    }
+
+    fn encode<W: Word>(self) -> W {
+        let bit = if self.bit { W::_1 } else { W::_0 };
+        let run = num::cast::<usize, W>(self.run) << 1;
+        let lits = num::cast::<usize, W>(self.lits) << (1 + Self::run_bits::<W>());
+        bit | run | lits
+    }
+}
+
+fn splat<W: Word>(bit: bool) -> W {
+    if bit {
+        W::FULL
+    } else {
+        W::NONE
+    }
+}
+
+impl<W: Word> Default for Ewah<W> {
+    fn default() -> Self {
+        Ewah::new()
+    }
+}
+
+impl<W: Word> Ewah<W> {
+    /// Constructs an empty bitmap.
+    pub fn new() -> Self {
+        Ewah {
+            buf: vec![Marker::default().encode()],
+            marker: 0,
+            words: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of words in the compressed buffer, including markers.
+    pub fn compressed_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    fn last(&self) -> Marker {
+        Marker::decode(self.buf[self.marker])
+    }
+
+    fn update(&mut self, marker: Marker) {
+        self.buf[self.marker] = marker.encode();
+    }
+
+    fn open(&mut self) -> Marker {
+        self.marker = self.buf.len();
+        self.buf.push(Marker::default().encode());
+        Marker::default()
+    }
+
+    /// Appends `n` words of `bit`.
+    fn push_run(&mut self, bit: bool, mut n: usize) {
+        while n > 0 {
+            let mut m = self.last();
+            if m.lits > 0 || (m.run > 0 && m.bit != bit) || m.run == Marker::max_run::<W>() {
+                m = self.open();
+            }
+            let k = cmp::min(n, Marker::max_run::<W>() - m.run);
+            m.bit = bit;
+            m.run += k;
+            self.update(m);
+            self.words += k;
+            n -= k;
+        }
+    }
+
+    /// Appends a word, a word that is all `0` or all `1` is appended as a run.
+    fn push_word(&mut self, word: W) {
+        if word == W::NONE {
+            self.push_run(false, 1);
+        } else if word == W::FULL {
+            self.push_run(true, 1);
+        } else {
+            let mut m = self.last();
+            if m.lits == Marker::max_lits::<W>() {
+                m = self.open();
+            }
+            m.lits += 1;
+            self.update(m);
+            self.buf.push(word);
+            self.words += 1;
+        }
+    }
+
+    fn cursor(&self) -> Cursor<'_, W> {
+        Cursor {
+            buf: &self.buf,
+            next: 0,
+            bit: false,
+            run: 0,
+            lits: &[],
+        }
+    }
+
+    fn zip_with(&self, that: &Self, f: fn(W, W) -> W) -> Self {
+        let mut out = Ewah::new();
+        let mut lhs = self.cursor();
+        let mut rhs = that.cursor();
+        loop {
+            // a finished cursor is regarded as an infinite run of `0`.
+            let (x, y) = match (lhs.peek(), rhs.peek()) {
+                (None, None) => break,
+                (x, y) => (
+                    x.unwrap_or(Chunk::Run(false, usize::MAX)),
+                    y.unwrap_or(Chunk::Run(false, usize::MAX)),
+                ),
+            };
+            let k = cmp::min(x.len(), y.len());
+
+            match (x, y) {
+                (Chunk::Run(a, _), Chunk::Run(b, _)) => {
+                    let word = f(splat(a), splat(b));
+                    out.push_run(word == W::FULL, k);
+                }
+                (Chunk::Run(a, _), Chunk::Lits(ys)) => out.push_lits(ys, k, |y| f(splat(a), y)),
+                (Chunk::Lits(xs), Chunk::Run(b, _)) => out.push_lits(xs, k, |x| f(x, splat(b))),
+                (Chunk::Lits(xs), Chunk::Lits(ys)) => {
+                    for (&x, &y) in xs.iter().zip(ys).take(k) {
+                        out.push_word(f(x, y));
+                    }
+                }
+            }
+            lhs.skip(k);
+            rhs.skip(k);
+        }
+        out.len = cmp::max(self.len, that.len);
+        out
+    }
+
+    // Applies `f` to the first `k` literals, and appends them as a run if `f` ignores literals.
+    fn push_lits<F: Fn(W) -> W>(&mut self, lits: &[W], k: usize, f: F) {
+        let none = f(W::NONE);
+        if none == f(W::FULL) && (none == W::NONE || none == W::FULL) {
+            self.push_run(none == W::FULL, k);
+        } else {
+            for &w in &lits[..k] {
+                self.push_word(f(w));
+            }
+        }
+    }
+
+    /// Returns the bitwise intersection, without decompressing runs.
+    pub fn and(&self, that: &Self) -> Self {
+        self.zip_with(that, |x, y| x & y)
+    }
+
+    /// Returns the bitwise union, without decompressing runs.
+    pub fn or(&self, that: &Self) -> Self {
+        self.zip_with(that, |x, y| x | y)
+    }
+
+    /// Returns the bitwise difference, without decompressing runs.
+    pub fn and_not(&self, that: &Self) -> Self {
+        self.zip_with(that, |x, y| x & !y)
+    }
+
+    /// Returns the bitwise symmetric difference, without decompressing runs.
+    pub fn xor(&self, that: &Self) -> Self {
+        self.zip_with(that, |x, y| x ^ y)
+    }
+
+    /// Returns `Mask` steps that have blocks of `T::LEN` words.
+    ///
+    /// Runs of `0` are skipped without allocating blocks.
+    pub fn steps<T: Words<Word = W>>(&self) -> Steps<'_, T> {
+        Steps {
+            cursor: self.cursor(),
+            pos: 0,
+            _words: std::marker::PhantomData,
+        }
+    }
+
+    // Folds chunks until `f` returns false, `f` takes the word position and a chunk.
+    fn scan<F: FnMut(usize, Chunk<'_, W>) -> bool>(&self, mut f: F) {
+        let mut cursor = self.cursor();
+        let mut pos = 0;
+        while let Some(chunk) = cursor.peek() {
+            let k = chunk.len();
+            if !f(pos, chunk) {
+                break;
+            }
+            cursor.skip(k);
+            pos += k;
+        }
+    }
+
+    // Counts `1` in `[0, p)`.
+    fn rank(&self, p: usize) -> usize {
+        let (q, r) = divrem!(p, W::BITS);
+        let mut rank = 0;
+        self.scan(|pos, chunk| {
+            let n = cmp::min(chunk.len(), q.saturating_sub(pos));
+            rank += match chunk {
+                Chunk::Run(bit, _) => bit as usize * n * W::BITS,
+                Chunk::Lits(lits) => lits[..n].count1(),
+            };
+            if pos + chunk.len() > q {
+                // `q` is in this chunk.
+                if r > 0 {
+                    rank += chunk.word(q - pos).rank1(..r);
+                }
+                false
+            } else {
+                true
+            }
+        });
+        rank
+    }
+}
+
+impl<W: Word> Bits for Ewah<W> {
+    #[inline]
+    fn size(&self) -> usize {
+        self.len
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.size());
+        let (q, r) = divrem!(i, W::BITS);
+        let mut bit = false;
+        self.scan(|pos, chunk| {
+            if q < pos + chunk.len() {
+                bit = chunk.word(q - pos).bit(r);
+                false
+            } else {
+                true
+            }
+        });
+        bit
+    }
+
+    fn count1(&self) -> usize {
+        let mut count = 0;
+        self.scan(|_, chunk| {
+            count += match chunk {
+                Chunk::Run(bit, n) => bit as usize * n * W::BITS,
+                Chunk::Lits(lits) => lits.count1(),
+            };
+            true
+        });
+        count
+    }
+
+    fn rank1<R: RangeBounds<usize>>(&self, range: R) -> usize {
+        match to_exclusive(&range, self.size()).expect("out of bounds") {
+            (0, j) => self.rank(j),
+            (i, j) => self.rank(j) - self.rank(i),
+        }
+    }
+
+    fn select1(&self, mut n: usize) -> Option<usize> {
+        let mut found = None;
+        self.scan(|pos, chunk| {
+            let count1 = match chunk {
+                Chunk::Run(bit, k) => bit as usize * k * W::BITS,
+                Chunk::Lits(lits) => lits.count1(),
+            };
+            if n < count1 {
+                found = match chunk {
+                    Chunk::Run(_, _) => Some(pos * W::BITS + n),
+                    Chunk::Lits(lits) => lits.select1(n).map(|i| pos * W::BITS + i),
+                };
+                false
+            } else {
+                n -= count1;
+                true
+            }
+        });
+        found
+    }
+}
+
+/// A run of words or literal words.
+#[derive(Debug, Clone, Copy)]
+enum Chunk<'a, W> {
+    Run(bool, usize),
+    Lits(&'a [W]),
+}
+
+impl<'a, W: Word> Chunk<'a, W> {
+    fn len(&self) -> usize {
+        match self {
+            Chunk::Run(_, n) => *n,
+            Chunk::Lits(lits) => lits.len(),
+        }
+    }
+
+    fn word(&self, i: usize) -> W {
+        match self {
+            Chunk::Run(bit, _) => splat(*bit),
+            Chunk::Lits(lits) => lits[i],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Cursor<'a, W> {
+    buf: &'a [W],
+    next: usize, // index of the next marker
+    bit: bool,
+    run: usize,    // remaining words of the current run
+    lits: &'a [W], // remaining literals of the current marker
+}
+
+impl<'a, W: Word> Cursor<'a, W> {
+    /// Returns the current chunk, reading the next marker if needed.
+    fn peek(&mut self) -> Option<Chunk<'a, W>> {
+        while self.run == 0 && self.lits.is_empty() {
+            if self.next >= self.buf.len() {
+                return None;
+            }
+            let m = Marker::decode(self.buf[self.next]);
+            let start = self.next + 1;
+            self.bit = m.bit;
+            self.run = m.run;
+            self.lits = &self.buf[start..start + m.lits];
+            self.next = start + m.lits;
+        }
+        Some(if self.run > 0 {
+            Chunk::Run(self.bit, self.run)
+        } else {
+            Chunk::Lits(self.lits)
+        })
+    }
+
+    /// Skips `n` words of the current chunk.
+    fn skip(&mut self, n: usize) {
+        if self.run > 0 {
+            self.run -= cmp::min(n, self.run);
+        } else if !self.lits.is_empty() {
+            self.lits = &self.lits[cmp::min(n, self.lits.len())..];
+        }
+    }
+}
+
+/// `Mask::Steps` for `Ewah`.
+#[derive(Debug, Clone)]
+pub struct Steps<'a, T: Words> {
+    cursor: Cursor<'a, T::Word>,
+    pos: usize, // word position of the cursor
+    _words: std::marker::PhantomData<T>,
+}
+
+impl<'a, T: Words> Iterator for Steps<'a, T> {
+    type Item = (usize, Cow<'a, [T::Word]>);
+    fn next(&mut self) -> Option<Self::Item> {
+        // skips runs of `0`.
+        while let Chunk::Run(false, n) = self.cursor.peek()? {
+            self.cursor.skip(n);
+            self.pos += n;
+        }
+
+        let index = self.pos / T::LEN;
+        let start = index * T::LEN;
+        let mut block = vec![<T::Word as Int>::NONE; T::LEN];
+        while self.pos < start + T::LEN {
+            let chunk = match self.cursor.peek() {
+                Some(chunk) => chunk,
+                None => break,
+            };
+            let k = cmp::min(chunk.len(), start + T::LEN - self.pos);
+            let offset = self.pos - start;
+            match chunk {
+                Chunk::Run(bit, _) => {
+                    for w in &mut block[offset..offset + k] {
+                        *w = splat(bit);
+                    }
+                }
+                Chunk::Lits(lits) => block[offset..offset + k].copy_from_slice(&lits[..k]),
+            }
+            self.cursor.skip(k);
+            self.pos += k;
+        }
+        Some((index, Cow::Owned(block)))
+    }
+}
+
+impl<'a, W: Word, B: ?Sized + Bits> From<&'a B> for Ewah<W> {
+    /// Compresses `bits` word by word.
+    fn from(bits: &'a B) -> Self {
+        let size = bits.size();
+        let mut ewah = Ewah::new();
+        for k in 0..blocks_by(size, W::BITS) {
+            let i = k * W::BITS;
+            ewah.push_word(bits.getn::<W>(i, cmp::min(W::BITS, size - i)));
+        }
+        ewah.len = size;
+        ewah
+    }
+}
+
+impl<W: Word> FromIterator<usize> for Ewah<W> {
+    /// Builds a bitmap from sorted positions of `1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if positions are not sorted.
+    fn from_iter<I: IntoIterator<Item = usize>>(iterable: I) -> Self {
+        let mut ewah = Ewah::new();
+        let mut index = 0;
+        let mut word = W::NONE;
+        for p in iterable {
+            let (q, r) = divrem!(p, W::BITS);
+            assert!(q >= index && p + 1 >= ewah.len, "positions must be sorted");
+            if q > index {
+                ewah.push_word(word);
+                ewah.push_run(false, q - index - 1);
+                word = W::NONE;
+                index = q;
+            }
+            word.put1(r);
+            ewah.len = p + 1;
+        }
+        if ewah.len > 0 {
+            ewah.push_word(word);
+        }
+        ewah
+    }
+}
+
+impl<'a, W: Word> FromMask<'a, [W]> for Ewah<W> {
+    fn from_mask<M: Mask<'a, Block = [W]>>(mask: M) -> Self {
+        let mut ewah = Ewah::new();
+        for (index, block) in mask.into_steps() {
+            ewah.push_step(index, &block, usize::MAX);
+        }
+        ewah.len = ewah.words * W::BITS;
+        ewah
+    }
+
+    fn from_mask_in<M: Mask<'a, Block = [W]>>(mask: M, len: usize) -> Self {
+        let words = blocks_by(len, W::BITS);
+        let mut ewah = Ewah::new();
+        for (index, block) in mask.within(..len).into_steps() {
+            ewah.push_step(index, &block, words);
+        }
+        ewah.push_run(false, words - ewah.words);
+        ewah.len = len;
+        ewah
+    }
+}
+
+impl<W: Word> Ewah<W> {
+    // Appends the words of a step at `index`, but no more than `words` words in total.
+    fn push_step(&mut self, index: usize, block: &[W], words: usize) {
+        let start = index * block.len();
+        assert!(start >= self.words, "steps must be sorted");
+        let start = cmp::min(start, words);
+        self.push_run(false, start - self.words);
+        for &word in block.iter().take(words - start) {
+            self.push_word(word);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! `expr` defines a boolean query over named masks.
+//!
+//! The textual grammar is the following, keywords are case sensitive.
+//!
+//! ```text
+//! or        = xor { "OR" xor }
+//! xor       = and { "XOR" and }
+//! and       = not { "AND" not }
+//! not       = "NOT" not | term
+//! term      = "(" or ")" | threshold | name
+//! threshold = "THRESHOLD" "(" number { "," or } ")"
+//! name      = a sequence of alphanumerics and `_`, `:`, `.`, `-`
+//! ```
+//!
+//! ```
+//! use compacts::{bits::{expr::Expr, Mask}, ops::{Bits, BitsMut}, BitMap};
+//! use std::collections::HashMap;
+//!
+//! let mut map = HashMap::new();
+//! for (name, bits) in vec![
+//!     ("country:JP", vec![1, 2, 3]),
+//!     ("country:US", vec![4, 5, 6]),
+//!     ("banned", vec![2, 5]),
+//! ] {
+//!     let mut bitmap = BitMap::<[u64; 1024]>::none(100000);
+//!     for i in bits {
+//!         bitmap.put1(i);
+//!     }
+//!     map.insert(name.to_string(), bitmap);
+//! }
+//!
+//! let expr = "(country:JP OR country:US) AND NOT banned".parse::<Expr>().unwrap();
+//! let found = expr.eval(&map, 100000).unwrap().materialize::<Vec<u64>>();
+//! assert_eq!(found.count1(), 4);
+//! assert!(found.bit(1) && !found.bit(2) && found.bit(6));
+//! ```
+
+use std::{
+    borrow::{Borrow, Cow},
+    collections::HashMap,
+    error, fmt,
+    hash::{BuildHasher, Hash},
+    str::FromStr,
+};
+
+use crate::{
+    bits::{Fold, Mask, Words},
+    ops::Bits,
+    BitMap,
+};
+
+/// A boolean expression over named bitmaps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// A bitmap referred by its name.
+    Leaf(String),
+    /// Complement of the expression.
+    Not(Box<Expr>),
+    /// Intersection of expressions.
+    And(Vec<Expr>),
+    /// Union of expressions.
+    Or(Vec<Expr>),
+    /// Symmetric difference of expressions.
+    Xor(Vec<Expr>),
+    /// Bits that are enabled in at least `k` of expressions.
+    Threshold(usize, Vec<Expr>),
+}
+
+/// An error in parsing or evaluating `Expr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// An unexpected token at the byte offset.
+    Unexpected(usize),
+    /// Input ends before the expression is completed.
+    UnexpectedEof,
+    /// A name that is not found in the source.
+    Unknown(String),
+    /// `Threshold` with `k` of zero, that every bit would satisfy.
+    ZeroThreshold,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unexpected(pos) => write!(f, "unexpected token at {}", pos),
+            Error::UnexpectedEof => write!(f, "unexpected end of input"),
+            Error::Unknown(name) => write!(f, "unknown name `{}`", name),
+            Error::ZeroThreshold => write!(f, "threshold must be greater than zero"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+type Folded<'a, T> = Fold<'a, Cow<'a, [<T as Words>::Word]>>;
+
+/// A source of named masks that `Expr` is evaluated against, such as a `HashMap` of bitmaps
+/// or a closure `Fn(&str) -> Option<M>`.
+///
+/// `T` is the block type of masks, that `Not` needs to generate full blocks.
+pub trait Source<'a, T: Words> {
+    /// A mask that a name refers to.
+    type Mask: 'a + Mask<'a, Block = [T::Word]>;
+
+    /// Returns the mask named `name`, or `None` if `name` is unknown.
+    fn get(&'a self, name: &str) -> Option<Self::Mask>;
+
+    /// Estimates the number of enabled bits of the mask named `name`.
+    ///
+    /// `Expr` orders intersections by it. By default, the mask is counted by `count_ones`.
+    fn count(&'a self, name: &str) -> Option<usize> {
+        self.get(name).map(|mask| mask.count_ones())
+    }
+}
+
+impl<'a, T, K, S> Source<'a, T> for HashMap<K, BitMap<T>, S>
+where
+    T: Words,
+    K: Borrow<str> + Hash + Eq,
+    S: BuildHasher,
+{
+    type Mask = &'a BitMap<T>;
+
+    fn get(&'a self, name: &str) -> Option<Self::Mask> {
+        HashMap::get(self, name)
+    }
+
+    fn count(&'a self, name: &str) -> Option<usize> {
+        HashMap::get(self, name).map(|bitmap| bitmap.count1())
+    }
+}
+
+impl<'a, T, M, F> Source<'a, T> for F
+where
+    T: Words,
+    M: 'a + Mask<'a, Block = [T::Word]>,
+    F: Fn(&str) -> Option<M>,
+{
+    type Mask = M;
+
+    fn get(&'a self, name: &str) -> Option<Self::Mask> {
+        self(name)
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression against `source`, in the universe of `[0, len)`.
+    ///
+    /// Operands of `And` are intersected from the sparsest one,
+    /// and negated operands of `And` are subtracted by `and_not` instead of complemented.
+    /// Returns `Error::ZeroThreshold` if `Threshold` has `k` of zero.
+    ///
+    /// ```
+    /// use compacts::{bits::{expr::Expr, Mask}, ops::{Bits, BitsMut}, BitMap};
+    /// let mut a = BitMap::<[u64; 1024]>::none(100000);
+    /// let mut b = BitMap::<[u64; 1024]>::none(100000);
+    /// a.put1(1);
+    /// a.put1(2);
+    /// b.put1(2);
+    ///
+    /// // a closure is a source too.
+    /// let source = |name: &str| match name {
+    ///     "a" => Some(&a),
+    ///     "b" => Some(&b),
+    ///     _ => None,
+    /// };
+    /// let expr = "a AND NOT b".parse::<Expr>().unwrap();
+    /// let found = expr.eval::<[u64; 1024], _>(&source, 100000).unwrap();
+    /// assert_eq!(found.materialize::<Vec<u64>>().select1(0), Some(1));
+    /// ```
+    pub fn eval<'a, T, S>(&self, source: &'a S, len: usize) -> Result<Folded<'a, T>, Error>
+    where
+        T: Words,
+        S: Source<'a, T>,
+    {
+        match self {
+            Expr::Leaf(name) => source
+                .get(name)
+                .map(|mask| Fold::new(mask.into_steps()))
+                .ok_or_else(|| Error::Unknown(name.clone())),
+
+            Expr::Not(expr) => Ok(Fold::new(expr.eval(source, len)?.not::<T>(len))),
+
+            Expr::And(exprs) => {
+                let (pos, neg): (Vec<&Expr>, Vec<&Expr>) =
+                    exprs.iter().partition(|e| !matches!(e, Expr::Not(_)));
+                // operands of negated ones, evaluated by reference.
+                let neg = neg
+                    .into_iter()
+                    .map(|e| match e {
+                        Expr::Not(e) => e.eval(source, len),
+                        _ => unreachable!(),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if pos.is_empty() {
+                    // ¬a ∧ ¬b = ¬(a ∨ b)
+                    return Ok(Fold::new(Fold::or(neg).not::<T>(len)));
+                }
+
+                let mut sorted = Vec::with_capacity(pos.len());
+                for e in pos {
+                    sorted.push((e, e.cost(source, len)?));
+                }
+                sorted.sort_by_key(|&(_, cost)| cost);
+
+                let mut sorted = sorted.into_iter();
+                let (head, _) = sorted.next().expect("not empty");
+                let mut fold = head.eval(source, len)?;
+                for (e, _) in sorted {
+                    fold = Fold::new(fold.and(e.eval(source, len)?));
+                }
+                if !neg.is_empty() {
+                    fold = Fold::new(fold.and_not(Fold::or(neg)));
+                }
+                Ok(fold)
+            }
+
+            Expr::Or(exprs) => Ok(Fold::or(Self::eval_all(exprs, source, len)?)),
+            Expr::Xor(exprs) => Ok(Fold::xor(Self::eval_all(exprs, source, len)?)),
+            Expr::Threshold(0, _) => Err(Error::ZeroThreshold),
+            // `k` greater than the number of operands is valid, and no bits satisfy it.
+            Expr::Threshold(k, exprs) => {
+                Ok(Fold::threshold(*k, Self::eval_all(exprs, source, len)?))
+            }
+        }
+    }
+
+    fn eval_all<'a, T, S>(
+        exprs: &[Expr],
+        source: &'a S,
+        len: usize,
+    ) -> Result<Vec<Folded<'a, T>>, Error>
+    where
+        T: Words,
+        S: Source<'a, T>,
+    {
+        exprs.iter().map(|e| e.eval(source, len)).collect()
+    }
+
+    /// Estimates the number of enabled bits.
+    fn cost<'a, T, S>(&self, source: &'a S, len: usize) -> Result<usize, Error>
+    where
+        T: Words,
+        S: Source<'a, T>,
+    {
+        let costs = |exprs: &[Expr]| -> Result<Vec<usize>, Error> {
+            exprs.iter().map(|e| e.cost(source, len)).collect()
+        };
+        Ok(match self {
+            Expr::Leaf(name) => source
+                .count(name)
+                .ok_or_else(|| Error::Unknown(name.clone()))?,
+            Expr::Not(expr) => len.saturating_sub(expr.cost(source, len)?),
+            Expr::And(exprs) => costs(exprs)?.into_iter().min().unwrap_or(0),
+            Expr::Or(exprs) | Expr::Xor(exprs) => {
+                std::cmp::min(len, costs(exprs)?.into_iter().sum())
+            }
+            Expr::Threshold(k, exprs) => costs(exprs)?.into_iter().sum::<usize>() / k.max(&1),
+        })
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join(f: &mut fmt::Formatter<'_>, exprs: &[Expr], sep: &str) -> fmt::Result {
+            write!(f, "(")?;
+            for (i, e) in exprs.iter().enumerate() {
+                if i > 0 {
+                    write!(f, "{}", sep)?;
+                }
+                write!(f, "{}", e)?;
+            }
+            write!(f, ")")
+        }
+
+        match self {
+            Expr::Leaf(name) => write!(f, "{}", name),
+            Expr::Not(expr) => write!(f, "NOT {}", expr),
+            Expr::And(exprs) => join(f, exprs, " AND "),
+            Expr::Or(exprs) => join(f, exprs, " OR "),
+            Expr::Xor(exprs) => join(f, exprs, " XOR "),
+            Expr::Threshold(k, exprs) => {
+                write!(f, "THRESHOLD({}", k)?;
+                for e in exprs {
+                    write!(f, ", {}", e)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// ```
+/// use compacts::bits::expr::{Error, Expr};
+/// let expr = "a AND NOT (b OR c)".parse::<Expr>().unwrap();
+/// assert_eq!(expr.to_string(), "(a AND NOT (b OR c))");
+/// assert_eq!(expr.to_string().parse::<Expr>(), Ok(expr));
+///
+/// assert_eq!("a AND".parse::<Expr>(), Err(Error::UnexpectedEof));
+/// assert_eq!("a b".parse::<Expr>(), Err(Error::Unexpected(2)));
+/// ```
+impl FromStr for Expr {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(&(at, _)) => Err(Error::Unexpected(at)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token<'s> {
+    Open,
+    Close,
+    Comma,
+    Word(&'s str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token<'_>)>, Error> {
+    let is_name = |c: char| c.is_alphanumeric() || "_:.-".contains(c);
+
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' => tokens.push((i, Token::Open)),
+            ')' => tokens.push((i, Token::Close)),
+            ',' => tokens.push((i, Token::Comma)),
+            c if c.is_whitespace() => {}
+            c if is_name(c) => {
+                let mut j = i + c.len_utf8();
+                while let Some(&(k, c)) = chars.peek() {
+                    if !is_name(c) {
+                        break;
+                    }
+                    j = k + c.len_utf8();
+                    chars.next();
+                }
+                tokens.push((i, Token::Word(&s[i..j])));
+            }
+            _ => return Err(Error::Unexpected(i)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'s> {
+    tokens: Vec<(usize, Token<'s>)>,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn peek(&self) -> Option<&Token<'s>> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token<'s>), Error> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or(Error::UnexpectedEof)
+    }
+
+    fn expect(&mut self, token: Token<'_>) -> Result<(), Error> {
+        let (at, next) = self.next()?;
+        if next == token {
+            Ok(())
+        } else {
+            Err(Error::Unexpected(at))
+        }
+    }
+
+    // Parses operands separated by `op`, and returns a single operand as is.
+    fn binary<F, G>(&mut self, op: &str, mut operand: F, make: G) -> Result<Expr, Error>
+    where
+        F: FnMut(&mut Self) -> Result<Expr, Error>,
+        G: FnOnce(Vec<Expr>) -> Expr,
+    {
+        let mut exprs = vec![operand(self)?];
+        while self.peek() == Some(&Token::Word(op)) {
+            self.pos += 1;
+            exprs.push(operand(self)?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().expect("not empty")
+        } else {
+            make(exprs)
+        })
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        self.binary("OR", Self::xor, Expr::Or)
+    }
+
+    fn xor(&mut self) -> Result<Expr, Error> {
+        self.binary("XOR", Self::and, Expr::Xor)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        self.binary("AND", Self::not, Expr::And)
+    }
+
+    fn not(&mut self) -> Result<Expr, Error> {
+        if self.peek() == Some(&Token::Word("NOT")) {
+            self.pos += 1;
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.term()
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, Error> {
+        match self.next()? {
+            (_, Token::Open) => {
+                let expr = self.or()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            (_, Token::Word("THRESHOLD")) => {
+                self.expect(Token::Open)?;
+                let k = match self.next()? {
+                    (at, Token::Word(k)) => match k.parse::<usize>() {
+                        Ok(k) if k > 0 => k,
+                        _ => return Err(Error::Unexpected(at)),
+                    },
+                    (at, _) => return Err(Error::Unexpected(at)),
+                };
+                let mut exprs = Vec::new();
+                while self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                    exprs.push(self.or()?);
+                }
+                self.expect(Token::Close)?;
+                Ok(Expr::Threshold(k, exprs))
+            }
+            (at, Token::Word(w)) if is_keyword(w) => Err(Error::Unexpected(at)),
+            (_, Token::Word(name)) => Ok(Expr::Leaf(name.to_string())),
+            (at, _) => Err(Error::Unexpected(at)),
+        }
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    matches!(word, "AND" | "OR" | "XOR" | "NOT" | "THRESHOLD")
+}
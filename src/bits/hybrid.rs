@@ -0,0 +1,393 @@
+//! `hybrid` defines a static bitvector that encodes each block by its density.
+
+use std::{
+    io::{self, Read, Write},
+    ops::RangeBounds,
+};
+
+use crate::{
+    bits::{
+        blocks_by,
+        persist::{self, Persist},
+        to_exclusive,
+    },
+    num::{self, cast, Word},
+    ops::*,
+};
+
+/// The number of bits in a block, positions in a block fit in `u16`.
+const BLOCK_SIZE: usize = 4096;
+
+/// The interval of select samples.
+const SAMPLE_SIZE: usize = 8192;
+
+/// `Hybrid` is an immutable bitvector, that stores each block as the smallest of
+/// plain words, a sorted list of positions, or a list of runs.
+///
+/// Rank and select first look up global samples to find a block,
+/// then ask the block in its own encoding.
+///
+/// ```
+/// use compacts::{bits::Hybrid, ops::{Bits, BitsMut}};
+/// let mut vec = vec![0u64; 1024];
+/// for i in (0..10000).step_by(3) {
+///     vec.put1(i); // dense
+/// }
+/// for i in (20000..30000).step_by(1000) {
+///     vec.put1(i); // sparse
+/// }
+/// for i in 40000..50000 {
+///     vec.put1(i); // a run
+/// }
+/// let hybrid = Hybrid::from(vec.clone());
+/// assert_eq!(hybrid.size(), vec.size());
+/// assert_eq!(hybrid.count1(), vec.count1());
+/// assert_eq!(hybrid.rank1(..45000), vec.rank1(..45000));
+/// assert_eq!(hybrid.select1(3340), vec.select1(3340));
+/// assert_eq!(hybrid.select0(20000), vec.select0(20000));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hybrid<W> {
+    blocks: Vec<Block<W>>,
+    // ranks[k] is the number of `1` in blocks[..k]
+    ranks: Vec<usize>,
+    // selects[k] is the block that has the `k * SAMPLE_SIZE`-th `1`
+    selects: Vec<usize>,
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Block<W> {
+    Words(Vec<W>),
+    Array(Vec<u16>),
+    Runs(Vec<(u16, u16)>), // inclusive
+}
+
+impl<W: Word> Block<W> {
+    /// Chooses the smallest encoding of `words` in bytes.
+    fn encode(words: &[W]) -> Self {
+        let size = words.size();
+        let ones = words.count1();
+        let runs = (0..size)
+            .filter(|&i| words.bit(i) && (i == 0 || !words.bit(i - 1)))
+            .count();
+
+        let plain = size / 8;
+        let array = ones * 2;
+        let runs = runs * 4;
+        if array < plain && array <= runs {
+            let mut vec = Vec::with_capacity(ones);
+            vec.extend((0..size).filter(|&i| words.bit(i)).map(cast::<usize, u16>));
+            Block::Array(vec)
+        } else if runs < plain {
+            let mut vec = Vec::<(u16, u16)>::with_capacity(runs / 4);
+            for i in (0..size).filter(|&i| words.bit(i)) {
+                match vec.last_mut() {
+                    Some(run) if run.1 as usize + 1 == i => run.1 += 1,
+                    _ => vec.push((cast(i), cast(i))),
+                }
+            }
+            Block::Runs(vec)
+        } else {
+            Block::Words(words.to_vec())
+        }
+    }
+
+    /// Checks that the block has `count` ones in `[0, bits)`, and its positions are sorted.
+    fn is_valid(&self, bits: usize, count: usize) -> bool {
+        match self {
+            Block::Words(words) => words.len() * W::BITS == bits && words.count1() == count,
+            Block::Array(array) => {
+                array.len() == count
+                    && array.windows(2).all(|w| w[0] < w[1])
+                    && array.last().map_or(0, |&x| x as usize + 1) <= bits
+            }
+            Block::Runs(runs) => {
+                runs.iter().all(|&(start, end)| start <= end)
+                    && runs.windows(2).all(|w| w[0].1 < w[1].0)
+                    && runs.last().map_or(0, |&(_, end)| end as usize + 1) <= bits
+                    && runs
+                        .iter()
+                        .map(|&(start, end)| (end - start) as usize + 1)
+                        .sum::<usize>()
+                        == count
+            }
+        }
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        match self {
+            Block::Words(words) => words.bit(i),
+            Block::Array(array) => array.binary_search(&cast(i)).is_ok(),
+            Block::Runs(runs) => {
+                let k = runs.partition_point(|&(_, end)| (end as usize) < i);
+                matches!(runs.get(k), Some(&(start, _)) if start as usize <= i)
+            }
+        }
+    }
+
+    /// Counts `1` in `[0, i)`.
+    fn rank1(&self, i: usize) -> usize {
+        match self {
+            Block::Words(words) => words.rank1(..i),
+            Block::Array(array) => array.partition_point(|&x| (x as usize) < i),
+            Block::Runs(runs) => runs
+                .iter()
+                .take_while(|&&(start, _)| (start as usize) < i)
+                .map(|&(start, end)| std::cmp::min(end as usize + 1, i) - start as usize)
+                .sum(),
+        }
+    }
+
+    fn select1(&self, mut n: usize) -> Option<usize> {
+        match self {
+            Block::Words(words) => words.select1(n),
+            Block::Array(array) => array.get(n).map(|&x| x as usize),
+            Block::Runs(runs) => {
+                for &(start, end) in runs {
+                    let len = (end - start) as usize + 1;
+                    if n < len {
+                        return Some(start as usize + n);
+                    }
+                    n -= len;
+                }
+                None
+            }
+        }
+    }
+
+    fn select0(&self, n: usize) -> Option<usize> {
+        match self {
+            Block::Words(words) => words.select0(n),
+            Block::Array(array) => {
+                // `array[k] - k` is the number of `0` before `array[k]`.
+                let k = array
+                    .iter()
+                    .enumerate()
+                    .take_while(|&(k, &x)| x as usize - k <= n)
+                    .count();
+                Some(n + k)
+            }
+            Block::Runs(runs) => {
+                let mut ones = 0;
+                for &(start, end) in runs {
+                    if n < start as usize - ones {
+                        break;
+                    }
+                    ones += (end - start) as usize + 1;
+                }
+                Some(n + ones)
+            }
+        }
+    }
+}
+
+impl<W: Word> From<Vec<W>> for Hybrid<W> {
+    fn from(data: Vec<W>) -> Self {
+        assert_eq!(BLOCK_SIZE % W::BITS, 0);
+        let len = data.size();
+
+        let mut blocks = Vec::with_capacity(blocks_by(len, BLOCK_SIZE));
+        let mut ranks = Vec::with_capacity(blocks.capacity() + 1);
+        let mut selects = Vec::new();
+        let mut sum = 0;
+        ranks.push(sum);
+        for (k, words) in data.chunks(BLOCK_SIZE / W::BITS).enumerate() {
+            let ones = words.count1();
+            while selects.len() * SAMPLE_SIZE < sum + ones {
+                selects.push(k);
+            }
+            sum += ones;
+            blocks.push(Block::encode(words));
+            ranks.push(sum);
+        }
+
+        Hybrid {
+            blocks,
+            ranks,
+            selects,
+            len,
+        }
+    }
+}
+
+impl<W: Word> Hybrid<W> {
+    /// Checks that each block fits in its bits and has as many `1` as `ranks` says,
+    /// and that `selects` points to the block of each sampled `1`.
+    fn is_valid(&self) -> bool {
+        if self.ranks[0] != 0 {
+            return false;
+        }
+        let blocks_ok = self.blocks.iter().enumerate().all(|(k, block)| {
+            let bits = std::cmp::min(BLOCK_SIZE, self.len - k * BLOCK_SIZE);
+            let count = match self.ranks[k + 1].checked_sub(self.ranks[k]) {
+                Some(count) => count,
+                None => return false,
+            };
+            block.is_valid(bits, count)
+        });
+        blocks_ok
+            && self.selects.len() == blocks_by(self.count1(), SAMPLE_SIZE)
+            && self.selects.iter().enumerate().all(|(q, &k)| {
+                let n = q * SAMPLE_SIZE;
+                k < self.blocks.len() && self.ranks[k] <= n && n < self.ranks[k + 1]
+            })
+    }
+
+    /// Finds the block that has the `n`-th `1` by samples, `n` must be less than `count1`.
+    fn block1(&self, n: usize) -> usize {
+        let (i, j) = self.sampled(n);
+        num::binary_search(i, j, |k| n < self.ranks[k]) - 1
+    }
+
+    // A range of `ranks` to search the `n`-th `1`.
+    fn sampled(&self, n: usize) -> (usize, usize) {
+        let q = n / SAMPLE_SIZE;
+        let i = self.selects[q];
+        let j = self
+            .selects
+            .get(q + 1)
+            .map_or(self.blocks.len(), |&k| k + 1);
+        (i, j + 1)
+    }
+}
+
+impl<W: Word> Bits for Hybrid<W> {
+    #[inline]
+    fn size(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn bit(&self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.size());
+        let (q, r) = divrem!(i, BLOCK_SIZE);
+        self.blocks[q].bit(r)
+    }
+
+    #[inline]
+    fn count1(&self) -> usize {
+        self.ranks[self.blocks.len()]
+    }
+
+    fn rank1<R: RangeBounds<usize>>(&self, range: R) -> usize {
+        let rank = |p: usize| {
+            let (q, r) = divrem!(p, BLOCK_SIZE);
+            if r == 0 {
+                self.ranks[q]
+            } else {
+                self.ranks[q] + self.blocks[q].rank1(r)
+            }
+        };
+        match to_exclusive(&range, self.size()).expect("out of bounds") {
+            (0, j) => rank(j),
+            (i, j) => rank(j) - rank(i),
+        }
+    }
+
+    fn select1(&self, n: usize) -> Option<usize> {
+        if n < self.count1() {
+            let k = self.block1(n);
+            let r = n - self.ranks[k];
+            self.blocks[k].select1(r).map(|p| k * BLOCK_SIZE + p)
+        } else {
+            None
+        }
+    }
+
+    fn select0(&self, n: usize) -> Option<usize> {
+        if n < self.count0() {
+            // the number of `0` in blocks[..k] is `k * BLOCK_SIZE - ranks[k]`
+            let zeros = |k: usize| k * BLOCK_SIZE - self.ranks[k];
+            let k = num::binary_search(0, self.blocks.len() + 1, |k| n < zeros(k)) - 1;
+            let r = n - zeros(k);
+            self.blocks[k].select0(r).map(|p| k * BLOCK_SIZE + p)
+        } else {
+            None
+        }
+    }
+}
+
+impl<W: Word> Persist for Hybrid<W> {
+    fn tag() -> String {
+        format!("Hybrid<u{}>", W::BITS)
+    }
+
+    /// Writes the size, samples, and blocks as a kind byte followed by its payload.
+    fn write_to<X: Write>(&self, w: &mut X) -> io::Result<()> {
+        persist::write_usize(w, self.len)?;
+        persist::write_usize(w, self.ranks.len())?;
+        for &rank in &self.ranks {
+            persist::write_usize(w, rank)?;
+        }
+        persist::write_usize(w, self.selects.len())?;
+        for &select in &self.selects {
+            persist::write_usize(w, select)?;
+        }
+        persist::write_usize(w, self.blocks.len())?;
+        for block in &self.blocks {
+            match block {
+                Block::Words(words) => {
+                    w.write_all(&[0])?;
+                    persist::write_words(w, words)?;
+                }
+                Block::Array(array) => {
+                    w.write_all(&[1])?;
+                    persist::write_words(w, array)?;
+                }
+                Block::Runs(runs) => {
+                    w.write_all(&[2])?;
+                    let flat = runs
+                        .iter()
+                        .flat_map(|&(i, j)| vec![i, j])
+                        .collect::<Vec<_>>();
+                    persist::write_words(w, &flat)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let len = persist::read_usize(r)?;
+        let mut ranks = Vec::new();
+        for _ in 0..persist::read_usize(r)? {
+            ranks.push(persist::read_usize(r)?);
+        }
+        let mut selects = Vec::new();
+        for _ in 0..persist::read_usize(r)? {
+            selects.push(persist::read_usize(r)?);
+        }
+        let mut blocks = Vec::new();
+        for _ in 0..persist::read_usize(r)? {
+            let mut kind = [0];
+            r.read_exact(&mut kind)?;
+            blocks.push(match kind[0] {
+                0 => Block::Words(persist::read_words(r)?),
+                1 => Block::Array(persist::read_words(r)?),
+                2 => {
+                    let flat = persist::read_words::<_, u16>(r)?;
+                    if flat.len() % 2 != 0 {
+                        return Err(persist::invalid("runs are not pairs"));
+                    }
+                    Block::Runs(flat.chunks_exact(2).map(|c| (c[0], c[1])).collect())
+                }
+                _ => return Err(persist::invalid("unknown block kind")),
+            });
+        }
+
+        if blocks.len() != blocks_by(len, BLOCK_SIZE) || ranks.len() != blocks.len() + 1 {
+            return Err(persist::invalid("samples don't match the bits"));
+        }
+        let hybrid = Hybrid {
+            blocks,
+            ranks,
+            selects,
+            len,
+        };
+        if !hybrid.is_valid() {
+            return Err(persist::invalid("blocks or samples are corrupted"));
+        }
+        Ok(hybrid)
+    }
+}
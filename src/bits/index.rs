@@ -0,0 +1,278 @@
+//! `index` defines a bitmap index for categorical columns.
+
+use std::{
+    borrow::{Borrow, Cow},
+    collections::HashMap,
+    hash::Hash,
+    iter::{empty, FromIterator},
+};
+
+use crate::{
+    bits::{blocks_by, BitArray, Fold, Mask, Words},
+    num::Int,
+    ops::*,
+    BitMap,
+};
+
+/// `BitmapIndex` keeps a bitmap of rows for each distinct key of a column.
+///
+/// A key's bitmap is either a `BitMap` or a `BitArray`, see `optimize`.
+/// `BitArray` is immutable, so the first `insert` or `remove` that touches a frozen key
+/// thaws the whole bitmap into `BitMap`, that costs `O(len)`. The key stays thawed until the
+/// next `optimize`, so mutations should be batched between calls to `optimize`.
+/// Dropping a key that has no rows any more costs `O(1)`.
+///
+/// ```
+/// use compacts::{bits::{index::BitmapIndex, Mask}, ops::Bits};
+/// let column = vec!["JP", "US", "JP", "FR", "US", "JP"];
+/// let mut index = column.into_iter().collect::<BitmapIndex<_, [u64; 1024]>>();
+/// assert_eq!(index.eq("JP").count_ones(), 3);
+/// assert_eq!(index.in_set(&["US", "FR"]).count_ones(), 3);
+/// assert_eq!(index.not_eq("JP").count_ones(), 3);
+///
+/// assert!(index.remove(0));
+/// index.insert(1, "JP");
+/// assert_eq!(index.eq("JP").materialize::<Vec<u64>>().select1(0), Some(1));
+/// assert_eq!(index.not_eq("JP").count_ones(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct BitmapIndex<K, T: Words> {
+    keys: HashMap<K, usize>,         // the slot of a key in `columns`
+    names: Vec<Option<K>>,           // the key of a slot, to drop it from `keys`
+    columns: Vec<Option<Column<T>>>, // `None` is a slot of a dropped key
+    free: Vec<usize>,                // slots to reuse
+    owners: Vec<usize>,              // the slot of the key that a live row has
+    rows: BitMap<T>,                 // live rows
+    len: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Column<T: Words> {
+    Map(BitMap<T>),
+    Array(BitArray<T::Word>),
+}
+
+impl<T: Words> Column<T> {
+    fn steps(&self) -> Fold<'_, Cow<'_, [T::Word]>> {
+        match self {
+            Column::Map(map) => Fold::new(map.into_steps()),
+            Column::Array(arr) => Fold::new(
+                arr.as_ref()
+                    .chunks(T::LEN)
+                    .enumerate()
+                    .map(|(i, chunk)| (i, Cow::Borrowed(chunk))),
+            ),
+        }
+    }
+
+    fn count1(&self) -> usize {
+        match self {
+            Column::Map(map) => map.count1(),
+            Column::Array(arr) => arr.count1(),
+        }
+    }
+
+    // `BitArray` is immutable, thaws it into `BitMap` before modification.
+    fn as_mut(&mut self) -> &mut BitMap<T> {
+        if let Column::Array(_) = self {
+            let mut map = self.steps().materialize::<BitMap<T>>();
+            map.optimize();
+            *self = Column::Map(map);
+        }
+        match self {
+            Column::Map(map) => map,
+            Column::Array(_) => unreachable!(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone, T: Words> Default for BitmapIndex<K, T> {
+    fn default() -> Self {
+        BitmapIndex::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, T: Words> BitmapIndex<K, T> {
+    /// Constructs an empty index.
+    pub fn new() -> Self {
+        BitmapIndex {
+            keys: HashMap::new(),
+            names: Vec::new(),
+            columns: Vec::new(),
+            free: Vec::new(),
+            owners: Vec::new(),
+            rows: BitMap::none(0),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of rows, including removed ones.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the index has no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of distinct keys.
+    pub fn keys(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns the bitmap of live rows.
+    pub fn rows(&self) -> &BitMap<T> {
+        &self.rows
+    }
+
+    /// Appends a row that has `key`, and returns the row.
+    pub fn push(&mut self, key: K) -> usize {
+        let row = self.len;
+        self.insert(row, key);
+        row
+    }
+
+    /// Sets `key` to `row`, replacing the key that `row` had before.
+    pub fn insert(&mut self, row: usize, key: K) {
+        self.remove(row);
+        self.len = std::cmp::max(self.len, row + 1);
+
+        let slot = match self.keys.get(&key) {
+            Some(&slot) => slot,
+            None => {
+                let slot = self.free.pop().unwrap_or_else(|| {
+                    self.columns.push(None);
+                    self.names.push(None);
+                    self.columns.len() - 1
+                });
+                self.names[slot] = Some(key.clone());
+                self.keys.insert(key, slot);
+                slot
+            }
+        };
+        let columns = &mut self.columns;
+        let map = columns[slot]
+            .get_or_insert_with(|| Column::Map(BitMap::none(0)))
+            .as_mut();
+        grow(map, row);
+        map.put1(row);
+        grow(&mut self.rows, row);
+        self.rows.put1(row);
+
+        if self.owners.len() <= row {
+            self.owners.resize(row + 1, 0);
+        }
+        self.owners[row] = slot;
+    }
+
+    /// Removes `row`, returns false if `row` is not live.
+    pub fn remove(&mut self, row: usize) -> bool {
+        if row >= self.rows.size() || !self.rows.bit(row) {
+            return false;
+        }
+        self.rows.put0(row);
+
+        let slot = self.owners[row];
+        let column = self.columns[slot].as_mut().expect("a live row has a key");
+        column.as_mut().put0(row);
+        if column.count1() == 0 {
+            // drops the key that has no rows any more, other keys are not touched.
+            self.columns[slot] = None;
+            self.free.push(slot);
+            let key = self.names[slot].take().expect("a live slot has a key");
+            self.keys.remove(&key);
+        }
+        true
+    }
+
+    /// Returns rows where the key is `key`.
+    pub fn eq<Q>(&self, key: &Q) -> Fold<'_, Cow<'_, [T::Word]>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self
+            .keys
+            .get(key)
+            .and_then(|&slot| self.columns[slot].as_ref())
+        {
+            Some(column) => column.steps(),
+            None => Fold::new(empty()),
+        }
+    }
+
+    /// Returns rows where the key is any of `keys`, as `IN (...)` does.
+    pub fn in_set<'a, Q, I>(&'a self, keys: I) -> Fold<'a, Cow<'a, [T::Word]>>
+    where
+        K: Borrow<Q>,
+        Q: 'a + ?Sized + Hash + Eq,
+        I: IntoIterator<Item = &'a Q>,
+    {
+        Fold::or(keys.into_iter().map(|key| self.eq(key)))
+    }
+
+    /// Returns live rows where the key is not `key`.
+    pub fn not_eq<Q>(&self, key: &Q) -> Fold<'_, Cow<'_, [T::Word]>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        Fold::new(self.rows.and_not(self.eq(key)))
+    }
+
+    /// Chooses the representation of each key's bitmap by its density.
+    ///
+    /// A dense bitmap, that has at least a bit per word on average, is frozen into `BitArray`
+    /// if compacting its empty and full blocks would not save half of the bytes,
+    /// because `BitArray` has rank/select samples.
+    /// Otherwise, the bitmap is kept as `BitMap`, and its empty and full blocks are compacted.
+    /// `insert` and `remove` thaw a `BitArray` into `BitMap` again.
+    pub fn optimize(&mut self) {
+        let blocks = blocks_by(self.len, T::BITS);
+        for column in self.columns.iter_mut().flatten() {
+            let dense = column.count1() * <T::Word as Int>::BITS >= self.len;
+            let freeze = dense && {
+                let kept = column
+                    .steps()
+                    .filter(|(_, block)| block.any() && !block.all())
+                    .count();
+                kept * 2 > blocks
+            };
+            if freeze {
+                if let Column::Map(map) = column {
+                    *column = Column::Array(map.into_steps().materialize());
+                }
+            } else {
+                column.as_mut().optimize();
+            }
+        }
+        self.rows.optimize();
+    }
+}
+
+// Grows `map` by a block at once, so that `map` has `row`.
+fn grow<T: Words>(map: &mut BitMap<T>, row: usize) {
+    if map.size() <= row {
+        map.resize(blocks_by(row + 1, T::BITS) * T::BITS);
+    }
+}
+
+impl<K: Hash + Eq + Clone, T: Words> FromIterator<K> for BitmapIndex<K, T> {
+    /// Builds an index in one pass over a column, a row is the position of a key.
+    fn from_iter<I: IntoIterator<Item = K>>(iterable: I) -> Self {
+        let mut index = BitmapIndex::new();
+        for key in iterable {
+            index.push(key);
+        }
+        index
+    }
+}
+
+impl<K: Hash + Eq + Clone, T: Words> Extend<K> for BitmapIndex<K, T> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iterable: I) {
+        for key in iterable {
+            self.push(key);
+        }
+    }
+}
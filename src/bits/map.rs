@@ -2,31 +2,166 @@
 
 use std::{
     borrow::Cow,
-    iter::{Enumerate, FromIterator},
+    io::{self, Read, Write},
+    iter::FromIterator,
+    mem,
     ops::RangeBounds,
-    slice,
 };
 
 use crate::{
-    bits::{bit_vec::BitVec, blocks_by, Mask, Words},
+    bits::{
+        bit_vec::BitVec,
+        blocks_by,
+        persist::{self, Persist},
+        to_exclusive, Difference, FromMask, Intersection, Mask, SymmetricDifference, Union, Words,
+    },
     fenwick::FenwickTree,
-    num::Word,
+    num::{self, Int, Word},
     ops::*,
 };
 
 /// `BitMap<T>`
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct BitMap<T: Words> {
-    tree: FenwickTree<usize>,     // prefix sum of `1`.
-    bits: BitVec<Option<Box<T>>>, // bit blocks, bits.size() is the capacity of `BitMap`
+    tree: FenwickTree<usize>, // prefix sum of `1`.
+    bits: BitVec<Entry<T>>,   // bit blocks, bits.size() is the capacity of `BitMap`
+    ones: Option<Box<T>>,     // all-ones block shared by every `Entry::Full`
+}
+
+/// A bit block of `BitMap`.
+///
+/// `None` and `Full` don't allocate, only `Bits` owns its words.
+#[derive(Debug, Clone)]
+enum Entry<T> {
+    None,
+    Full,
+    Bits(Box<T>),
 }
 
 impl<T: Words> BitMap<T> {
     /// Reserves specified capacity by multiples of T::SIZE, such that `BitMap` has at least `n` bits.
     pub fn none(n: usize) -> Self {
-        let bits = BitVec::from_fn(n, || None);
+        let bits = BitVec::from_fn(n, || Entry::None);
         let tree = FenwickTree::with_default(blocks_by(n, T::BITS));
-        BitMap { tree, bits }
+        BitMap {
+            tree,
+            bits,
+            ones: None,
+        }
+    }
+
+    /// Resizes the `BitMap` in-place so that it has `n` bits.
+    ///
+    /// If `n` is less than the current size, bits at `n` or later are dropped.
+    ///
+    /// ```
+    /// use compacts::{BitMap, ops::{Bits, BitsMut}};
+    /// let mut map = BitMap::<[u64; 1024]>::none(100);
+    /// map.put1(10);
+    /// map.resize(100000);
+    /// map.put1(70000);
+    /// assert_eq!(map.size(), 100000);
+    /// assert_eq!(map.count1(), 2);
+    /// map.resize(11);
+    /// assert_eq!(map.count1(), 1);
+    /// map.resize(100);
+    /// assert_eq!(map.count1(), 1);
+    /// ```
+    pub fn resize(&mut self, n: usize) {
+        let blocks = blocks_by(n, T::BITS);
+        let size = self.size();
+        if n < size {
+            // disables bits that remain in the new last block
+            for i in n..std::cmp::min(size, blocks * T::BITS) {
+                self.put0(i);
+            }
+            self.tree.tree.truncate(blocks + 1);
+        } else {
+            let len = self.bits.buf.len();
+            self.tree.extend_by_default(blocks - len, |x| x);
+        }
+        self.bits.resize_with(n, || Entry::None);
+    }
+
+    /// Releases blocks that have no enabled bits, and replaces blocks that have no disabled bits
+    /// with the all-ones block shared in this `BitMap`.
+    ///
+    /// Returns the number of bytes freed.
+    ///
+    /// ```
+    /// use compacts::{BitMap, ops::{Bits, BitsMut}};
+    /// let mut map = BitMap::<[u64; 1024]>::none(65536 * 4);
+    /// for i in 0..65536 * 2 {
+    ///     map.put1(i); // block 0 and 1 are full
+    /// }
+    /// map.put1(65536 * 2); // block 2 has only one bit
+    /// map.put1(65536 * 3);
+    /// map.put0(65536 * 3); // block 3 is empty
+    ///
+    /// // block 3 is released, block 0 and 1 are merged into one shared block.
+    /// assert_eq!(map.optimize(), 8192 * 2);
+    /// assert_eq!(map.optimize(), 0);
+    /// assert_eq!(map.count1(), 65536 * 2 + 1);
+    /// assert_eq!(map.rank1(..65536 * 2), 65536 * 2);
+    ///
+    /// map.put0(0); // block 0 is allocated again
+    /// assert_eq!(map.count1(), 65536 * 2);
+    /// assert_eq!(map.select1(0), Some(1));
+    /// ```
+    pub fn optimize(&mut self) -> usize {
+        let mut released = 0; // the number of blocks released
+        let mut allocated = 0; // the number of blocks allocated
+
+        for (i, entry) in self.bits.buf.iter_mut().enumerate() {
+            if let Entry::Bits(_) = entry {
+                let count1 = self.tree.get(i);
+                if count1 == 0 {
+                    *entry = Entry::None;
+                    released += 1;
+                } else if count1 == T::BITS {
+                    if self.ones.is_none() {
+                        self.ones = Some(Box::new(T::splat(<T::Word as Int>::FULL)));
+                        allocated += 1;
+                    }
+                    *entry = Entry::Full;
+                    released += 1;
+                }
+            }
+        }
+
+        (released - allocated) * mem::size_of::<T>()
+    }
+
+    /// Optimizes blocks as `optimize` does, and shrinks the capacity as much as possible.
+    ///
+    /// Returns the number of bytes freed.
+    ///
+    /// ```
+    /// use compacts::{BitMap, ops::{Bits, BitsMut}};
+    /// let mut map = BitMap::<[u64; 1024]>::none(65536 * 2);
+    /// map.put1(0);
+    /// map.put1(65536);
+    /// map.put0(65536);
+    /// assert!(map.shrink_to_fit() >= 8192);
+    /// assert_eq!(map.shrink_to_fit(), 0);
+    /// assert_eq!(map.count1(), 1);
+    /// ```
+    pub fn shrink_to_fit(&mut self) -> usize {
+        let mut freed = self.optimize();
+
+        let full = self.bits.buf.iter().any(|e| matches!(e, Entry::Full));
+        if !full && self.ones.take().is_some() {
+            freed += mem::size_of::<T>();
+        }
+
+        let entries = self.bits.buf.capacity();
+        let sums = self.tree.tree.capacity();
+        self.bits.shrink_to_fit();
+        self.tree.tree.shrink_to_fit();
+        freed += (entries - self.bits.buf.capacity()) * mem::size_of::<Entry<T>>();
+        freed += (sums - self.tree.tree.capacity()) * mem::size_of::<usize>();
+
+        freed
     }
 
     // // pub(crate) fn from_buf(bits: Vec<Option<Box<T>>>, size: usize) -> Self {
@@ -70,6 +205,159 @@ impl<T: Words> BitMap<T> {
     // }
 }
 
+impl<T: Words> PartialEq for BitMap<T> {
+    fn eq(&self, that: &Self) -> bool {
+        self.tree == that.tree && self.bits == that.bits
+    }
+}
+impl<T: Words> Eq for BitMap<T> {}
+
+impl<T: Words> PartialEq for Entry<T> {
+    fn eq(&self, that: &Self) -> bool {
+        match (self, that) {
+            (Entry::None, Entry::None) | (Entry::Full, Entry::Full) => true,
+            (Entry::Bits(a), Entry::Bits(b)) => a.as_ref_words() == b.as_ref_words(),
+            (Entry::None, Entry::Bits(b)) | (Entry::Bits(b), Entry::None) => !b.any(),
+            (Entry::Full, Entry::Bits(b)) | (Entry::Bits(b), Entry::Full) => b.all(),
+            (Entry::None, Entry::Full) | (Entry::Full, Entry::None) => false,
+        }
+    }
+}
+impl<T: Words> Eq for Entry<T> {}
+
+impl<T: Words> Entry<T> {
+    /// Returns a mutable reference to the words, allocating them if needed.
+    fn make_mut(&mut self) -> &mut T {
+        match self {
+            Entry::None => *self = Entry::Bits(Box::new(T::none())),
+            Entry::Full => *self = Entry::Bits(Box::new(T::splat(<T::Word as Int>::FULL))),
+            Entry::Bits(_) => {}
+        }
+        match self {
+            Entry::Bits(b) => b,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<T: Words> FixedBits for Entry<T> {
+    const SIZE: usize = T::SIZE;
+    #[inline]
+    fn none() -> Self {
+        Entry::None
+    }
+}
+
+impl<T: Words> Bits for Entry<T> {
+    #[inline]
+    fn size(&self) -> usize {
+        Self::SIZE
+    }
+
+    #[inline]
+    fn bit(&self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.size());
+        match self {
+            Entry::None => false,
+            Entry::Full => true,
+            Entry::Bits(b) => b.bit(i),
+        }
+    }
+
+    #[inline]
+    fn getn<W: Word>(&self, i: usize, n: usize) -> W {
+        BOUNDS_CHECK!(n <= W::SIZE && i < self.size() && i + n <= self.size());
+        match self {
+            Entry::None => W::NONE,
+            Entry::Full => num::mask1(n),
+            Entry::Bits(b) => b.getn(i, n),
+        }
+    }
+
+    #[inline]
+    fn count1(&self) -> usize {
+        match self {
+            Entry::None => 0,
+            Entry::Full => Self::SIZE,
+            Entry::Bits(b) => b.count1(),
+        }
+    }
+
+    #[inline]
+    fn rank1<R: RangeBounds<usize>>(&self, range: R) -> usize {
+        match self {
+            Entry::None => 0,
+            Entry::Full => {
+                let (i, j) = to_exclusive(&range, Self::SIZE).expect("out of bounds");
+                j - i
+            }
+            Entry::Bits(b) => b.rank1(range),
+        }
+    }
+
+    #[inline]
+    fn all(&self) -> bool {
+        match self {
+            Entry::None => false,
+            Entry::Full => true,
+            Entry::Bits(b) => b.all(),
+        }
+    }
+
+    #[inline]
+    fn any(&self) -> bool {
+        match self {
+            Entry::None => false,
+            Entry::Full => true,
+            Entry::Bits(b) => b.any(),
+        }
+    }
+
+    #[inline]
+    fn select1(&self, n: usize) -> Option<usize> {
+        match self {
+            Entry::None => None,
+            Entry::Full => Some(n).filter(|&n| n < Self::SIZE),
+            Entry::Bits(b) => b.select1(n),
+        }
+    }
+
+    #[inline]
+    fn select0(&self, n: usize) -> Option<usize> {
+        match self {
+            Entry::None => Some(n).filter(|&n| n < Self::SIZE),
+            Entry::Full => None,
+            Entry::Bits(b) => b.select0(n),
+        }
+    }
+}
+
+impl<T: Words> BitsMut for Entry<T> {
+    #[inline]
+    fn put1(&mut self, i: usize) {
+        BOUNDS_CHECK!(i < self.size());
+        if let Entry::Full = self {
+            return;
+        }
+        self.make_mut().put1(i);
+    }
+
+    #[inline]
+    fn put0(&mut self, i: usize) {
+        BOUNDS_CHECK!(i < self.size());
+        if let Entry::None = self {
+            return;
+        }
+        self.make_mut().put0(i);
+    }
+
+    #[inline]
+    fn flip(&mut self, i: usize) {
+        BOUNDS_CHECK!(i < self.size());
+        self.make_mut().flip(i);
+    }
+}
+
 impl<T: Words> Bits for BitMap<T> {
     #[inline]
     fn size(&self) -> usize {
@@ -175,15 +463,117 @@ impl<T: Words> BitsMut for BitMap<T> {
     }
 }
 
+impl<T: Words> BitMap<T> {
+    /// Applies `f` to each pair of blocks, and fixes the prefix sums of blocks.
+    fn zip_with<F>(&mut self, that: &BitMap<T>, mut f: F)
+    where
+        F: FnMut(&mut Entry<T>, &Entry<T>),
+    {
+        assert_eq!(self.size(), that.size());
+        if self.ones.is_none() {
+            self.ones = that.ones.clone();
+        }
+        for (i, (lhs, rhs)) in self.bits.buf.iter_mut().zip(&that.bits.buf).enumerate() {
+            f(lhs, rhs);
+            self.tree.set(i, lhs.count1());
+        }
+    }
+}
+
+impl<T: Words> Intersection<BitMap<T>> for BitMap<T> {
+    /// ```
+    /// use compacts::{BitMap, bits::Intersection, ops::{Bits, BitsMut}};
+    /// let mut a = BitMap::<[u64; 1024]>::none(100000);
+    /// let mut b = BitMap::<[u64; 1024]>::none(100000);
+    /// a.put1(10);
+    /// a.put1(70000);
+    /// b.put1(70000);
+    /// b.put1(80000);
+    /// a.intersection(&b);
+    /// assert_eq!(a.count1(), 1);
+    /// assert_eq!(a.select1(0), Some(70000));
+    /// ```
+    fn intersection(&mut self, that: &BitMap<T>) {
+        self.zip_with(that, |lhs, rhs| match (&mut *lhs, rhs) {
+            (_, Entry::Full) | (Entry::None, _) => {}
+            (_, Entry::None) => *lhs = Entry::None,
+            (Entry::Full, Entry::Bits(b)) => *lhs = Entry::Bits(b.clone()),
+            (Entry::Bits(a), Entry::Bits(b)) => {
+                a.as_mut_words().intersection(b.as_ref_words());
+            }
+        })
+    }
+}
+
+impl<T: Words> Union<BitMap<T>> for BitMap<T> {
+    fn union(&mut self, that: &BitMap<T>) {
+        self.zip_with(that, |lhs, rhs| match (&mut *lhs, rhs) {
+            (_, Entry::None) | (Entry::Full, _) => {}
+            (_, Entry::Full) => *lhs = Entry::Full,
+            (Entry::None, Entry::Bits(b)) => *lhs = Entry::Bits(b.clone()),
+            (Entry::Bits(a), Entry::Bits(b)) => {
+                a.as_mut_words().union(b.as_ref_words());
+            }
+        })
+    }
+}
+
+impl<T: Words> Difference<BitMap<T>> for BitMap<T> {
+    fn difference(&mut self, that: &BitMap<T>) {
+        self.zip_with(that, |lhs, rhs| match (&mut *lhs, rhs) {
+            (_, Entry::None) | (Entry::None, _) => {}
+            (_, Entry::Full) => *lhs = Entry::None,
+            (Entry::Full, Entry::Bits(b)) => {
+                let mut arr = T::splat(<T::Word as Int>::FULL);
+                arr.as_mut_words().difference(b.as_ref_words());
+                *lhs = Entry::Bits(Box::new(arr));
+            }
+            (Entry::Bits(a), Entry::Bits(b)) => {
+                a.as_mut_words().difference(b.as_ref_words());
+            }
+        })
+    }
+}
+
+impl<T: Words> SymmetricDifference<BitMap<T>> for BitMap<T> {
+    fn symmetric_difference(&mut self, that: &BitMap<T>) {
+        self.zip_with(that, |lhs, rhs| match (&mut *lhs, rhs) {
+            (_, Entry::None) => {}
+            (Entry::None, _) => *lhs = rhs.clone(),
+            (Entry::Full, Entry::Full) => *lhs = Entry::None,
+            (Entry::Full, Entry::Bits(b)) => {
+                let mut arr = T::splat(<T::Word as Int>::FULL);
+                arr.as_mut_words().difference(b.as_ref_words());
+                *lhs = Entry::Bits(Box::new(arr));
+            }
+            (Entry::Bits(a), Entry::Full) => {
+                for w in a.as_mut_words() {
+                    *w = !*w;
+                }
+            }
+            (Entry::Bits(a), Entry::Bits(b)) => {
+                a.as_mut_words().symmetric_difference(b.as_ref_words());
+            }
+        })
+    }
+}
+
 impl<'a, T: Words> Mask<'a> for &'a BitMap<T> {
     type Block = [T::Word];
     type Steps = Steps<'a, T>;
     fn into_steps(self) -> Self::Steps {
         Steps(Iter {
-            // TODO
-            iter: self.bits.buf.iter().enumerate(),
+            entries: &self.bits.buf,
+            pos: 0,
+            ones: self.ones.as_ref().map(|b| b.as_ref_words()),
         })
     }
+
+    /// Jumps to the entry at `index`, because entries are indexed by blocks.
+    fn seek(steps: &mut Self::Steps, index: usize) -> Option<(usize, Cow<'a, Self::Block>)> {
+        steps.0.pos = std::cmp::max(steps.0.pos, index);
+        steps.next()
+    }
 }
 
 /// `Mask::Steps` for `BitMap`.
@@ -192,14 +582,16 @@ pub struct Steps<'a, T: Words>(Iter<'a, T>);
 
 #[derive(Debug, Clone)]
 struct Iter<'a, T: Words> {
-    iter: Enumerate<slice::Iter<'a, Option<Box<T>>>>,
+    entries: &'a [Entry<T>],
+    pos: usize, // the index of the next entry
+    ones: Option<&'a [T::Word]>,
 }
 
 impl<'a, T: Words> Iterator for Steps<'a, T> {
     type Item = (usize, Cow<'a, [T::Word]>);
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(|(i, s)| (i, Cow::Borrowed(s)))
+        self.0.next()
     }
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -208,47 +600,139 @@ impl<'a, T: Words> Iterator for Steps<'a, T> {
 }
 
 impl<'a, T: Words> Iterator for Iter<'a, T> {
-    type Item = (usize, &'a [T::Word]);
+    type Item = (usize, Cow<'a, [T::Word]>);
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter
-            .find_map(|(i, opt)| opt.as_ref().map(|s| (i, s.as_ref_words())))
+        while let Some(entry) = self.entries.get(self.pos) {
+            let i = self.pos;
+            self.pos += 1;
+            match entry {
+                Entry::None => {}
+                // `Full` borrows the shared all-ones block, that `optimize` and `zip_with` set
+                // before they make a `Full`. An all-ones block is built if it's missing anyway.
+                Entry::Full => {
+                    let ones = match self.ones {
+                        Some(ones) => Cow::Borrowed(ones),
+                        None => Cow::Owned(vec![<T::Word as Int>::FULL; T::LEN]),
+                    };
+                    return Some((i, ones));
+                }
+                Entry::Bits(b) => return Some((i, Cow::Borrowed(b.as_ref_words()))),
+            }
+        }
+        None
     }
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+        (0, Some(self.entries.len().saturating_sub(self.pos)))
     }
 }
 
-impl<'a, T: Words> FromIterator<(usize, Cow<'a, [T::Word]>)> for BitMap<T> {
-    fn from_iter<I>(iterable: I) -> Self
-    where
-        I: IntoIterator<Item = (usize, Cow<'a, [T::Word]>)>,
-    {
-        let mut len = 0;
-        let mut buf = Vec::new();
-        for (index, cow) in iterable {
-            if index > buf.len() {
-                buf.resize(index, None);
-            }
+impl<'a, T: Words> FromMask<'a, [T::Word]> for BitMap<T> {
+    fn from_mask<M: Mask<'a, Block = [T::Word]>>(mask: M) -> Self {
+        let buf = collect(mask, 0);
+        let len = buf.len() * T::BITS;
+        BitMap::from_entries(buf, len)
+    }
 
-            buf.insert(index, {
-                let mut arr = T::none();
-                arr.as_mut_words().copy_from_slice(cow.as_ref());
-                Some(Box::new(arr))
-            });
+    fn from_mask_in<M: Mask<'a, Block = [T::Word]>>(mask: M, len: usize) -> Self {
+        let buf = collect(mask.within(..len), blocks_by(len, T::BITS));
+        debug_assert_eq!(buf.len(), blocks_by(len, T::BITS));
+        BitMap::from_entries(buf, len)
+    }
+}
 
-            len += T::BITS;
+// Collects steps into at least `blocks` entries.
+fn collect<'a, T, M>(mask: M, blocks: usize) -> Vec<Entry<T>>
+where
+    T: Words,
+    M: Mask<'a, Block = [T::Word]>,
+{
+    let steps = mask.into_steps();
+    let mut buf = Vec::with_capacity(std::cmp::max(blocks, steps.size_hint().0));
+    buf.resize_with(blocks, || Entry::None);
+    for (index, block) in steps {
+        assert_eq!(block.len(), T::LEN);
+        if buf.len() <= index {
+            buf.resize_with(index + 1, || Entry::None);
         }
+        if block.any() {
+            let mut arr = T::none();
+            arr.as_mut_words().copy_from_slice(&block);
+            buf[index] = Entry::Bits(Box::new(arr));
+        }
+    }
+    buf
+}
 
-        buf.shrink_to_fit();
-
+impl<T: Words> BitMap<T> {
+    // Builds the prefix sums of `buf`, that has no `Entry::Full`.
+    fn from_entries(buf: Vec<Entry<T>>, len: usize) -> Self {
         let mut tree = FenwickTree::with_default(buf.len());
-        for (i, p) in buf.iter().enumerate() {
-            tree.add(i, p.count1());
+        for (i, entry) in buf.iter().enumerate() {
+            if let Entry::Bits(b) = entry {
+                tree.add(i, b.as_ref_words().count1());
+            }
         }
-
         let bits = BitVec { buf, len };
-        BitMap { tree, bits }
+        BitMap {
+            tree,
+            bits,
+            ones: None,
+        }
+    }
+}
+
+impl<T: Words> Persist for BitMap<T> {
+    fn tag() -> String {
+        format!("BitMap<[u{}; {}]>", <T::Word as Int>::BITS, T::LEN)
+    }
+
+    /// Writes the size, and each non-empty block as its index followed by its words.
+    ///
+    /// `BitMap` has no samples but the prefix sums of blocks, that are rebuilt in reading.
+    /// Full blocks are written as words, and shared again in reading.
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        persist::write_usize(w, self.size())?;
+        let blocks = self.bits.buf.iter();
+        persist::write_usize(w, blocks.filter(|e| !matches!(e, Entry::None)).count())?;
+        for (index, block) in self.into_steps() {
+            persist::write_usize(w, index)?;
+            persist::write_words(w, &block)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let size = persist::read_usize(r)?;
+        let mut map = BitMap::<T>::none(size);
+        let mut next = 0; // blocks are sorted by index
+        for _ in 0..persist::read_usize(r)? {
+            let index = persist::read_usize(r)?;
+            let words = persist::read_words::<_, T::Word>(r)?;
+            if index < next || index >= map.bits.buf.len() || words.len() != T::LEN {
+                return Err(persist::invalid("blocks don't match the size"));
+            }
+            let tail = std::cmp::min(T::BITS, size - index * T::BITS);
+            if tail < T::BITS && words.rank1(tail..) > 0 {
+                return Err(persist::invalid("bits out of the size"));
+            }
+            let mut arr = T::none();
+            arr.as_mut_words().copy_from_slice(&words);
+            map.tree.add(index, words.count1());
+            map.bits.buf[index] = Entry::Bits(Box::new(arr));
+            next = index + 1;
+        }
+        map.optimize();
+        Ok(map)
+    }
+}
+
+impl<'a, T: Words> FromIterator<(usize, Cow<'a, [T::Word]>)> for BitMap<T> {
+    fn from_iter<I>(iterable: I) -> Self
+    where
+        I: IntoIterator<Item = (usize, Cow<'a, [T::Word]>)>,
+    {
+        Self::from_mask(iterable)
     }
 }
@@ -2,8 +2,21 @@
 
 use std::{
     borrow::Cow,
-    cmp::Ordering::{self, Equal, Greater, Less},
+    cmp::{
+        self,
+        Ordering::{self, Equal, Greater, Less},
+        Reverse,
+    },
+    collections::BinaryHeap,
     iter::{empty, Peekable},
+    marker::PhantomData,
+    ops::RangeBounds,
+};
+
+use crate::{
+    bits::{blocks_by, to_exclusive, Words},
+    num::{self, Int, Word},
+    ops::Bits,
 };
 
 /// A trait for bitwise masking.
@@ -17,6 +30,14 @@ pub trait Mask<'a>: Sized {
     /// An iterator over bit blocks.
     fn into_steps(self) -> Self::Steps;
 
+    /// Advances `steps` to the first step whose index is at least `index`, and returns it.
+    ///
+    /// By default, `seek` calls `next` until it reaches `index`.
+    /// Masks of indexed blocks, such as `&BitMap`, jump to `index` instead.
+    fn seek(steps: &mut Self::Steps, index: usize) -> Option<(usize, Cow<'a, Self::Block>)> {
+        steps.find(|&(i, _)| i >= index)
+    }
+
     /// Returns an iterator that performs bitwise intersection.
     fn and<Rhs: Mask<'a>>(self, that: Rhs) -> And<'a, Self, Rhs> {
         And::new(self, that)
@@ -36,6 +57,154 @@ pub trait Mask<'a>: Sized {
     fn xor<Rhs: Mask<'a>>(self, that: Rhs) -> Xor<'a, Self, Rhs> {
         Xor::new(self, that)
     }
+
+    /// Returns an iterator that performs bitwise complement in the universe of `[0, len)`.
+    ///
+    /// Because a missing step is regarded as an empty block, `not` needs to know the block type
+    /// `T` to generate full blocks for missing indexes. The last block is trimmed to `len`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a step of `self` doesn't have `T::LEN` words, i.e. `T` is not the block type.
+    ///
+    /// ```
+    /// use compacts::{bits::Mask, ops::{Bits, BitsMut}, BitMap};
+    /// let mut active = BitMap::<[u64; 1024]>::none(200000);
+    /// let mut deleted = BitMap::<[u64; 1024]>::none(200000);
+    /// active.put1(10);
+    /// active.put1(100000);
+    /// deleted.put1(10);
+    ///
+    /// let not = deleted.not::<[u64; 1024]>(200000).materialize::<BitMap<[u64; 1024]>>();
+    /// assert_eq!(not.count1(), 200000 - 1);
+    /// assert!(!not.bit(10));
+    /// assert_eq!(active.and(deleted.not::<[u64; 1024]>(200000)).count_ones(), 1);
+    /// ```
+    fn not<T: Words>(self, len: usize) -> Not<'a, Self, T>
+    where
+        Self: Mask<'a, Block = [T::Word]>,
+    {
+        Not::new(self, len)
+    }
+
+    /// Returns an iterator that clips steps to `range`.
+    ///
+    /// ```
+    /// use compacts::{bits::Mask, ops::{Bits, BitsMut}, BitMap};
+    /// let mut active = BitMap::<[u64; 1024]>::none(200000);
+    /// let mut deleted = BitMap::<[u64; 1024]>::none(200000);
+    /// for i in (0..200000).step_by(1000) {
+    ///     active.put1(i);
+    /// }
+    /// deleted.put1(70000);
+    ///
+    /// let shard = active.and_not(&deleted).within(65536..100000);
+    /// assert_eq!(shard.materialize::<Vec<u64>>().count1(), 33);
+    /// ```
+    fn within<W: Word, R: RangeBounds<usize>>(self, range: R) -> Within<'a, Self>
+    where
+        Self: Mask<'a, Block = [W]>,
+    {
+        Within::new(self, range)
+    }
+
+    /// Consumes steps and builds a bit container, such as `BitMap`, `BitArray`, `Pop` or `Vec`.
+    ///
+    /// ```
+    /// use compacts::{bits::Mask, ops::{Bits, BitsMut}, BitArray, BitMap, Pop};
+    /// let mut a = BitMap::<[u64; 1024]>::none(200000);
+    /// let mut b = BitMap::<[u64; 1024]>::none(200000);
+    /// a.put1(10);
+    /// a.put1(150000);
+    /// b.put1(150000);
+    ///
+    /// let vec = a.and(&b).materialize::<Vec<u64>>();
+    /// assert_eq!(vec.len(), 3 * 1024);
+    /// assert_eq!(vec.select1(0), Some(150000));
+    ///
+    /// let arr = a.or(&b).materialize::<BitArray<u64>>();
+    /// assert_eq!(arr.count1(), 2);
+    /// let pop = a.xor(&b).materialize::<Pop<u64>>();
+    /// assert_eq!(pop.select1(0), Some(10));
+    /// let map = a.and_not(&b).materialize::<BitMap<[u64; 1024]>>();
+    /// assert_eq!(map.rank1(..), 1);
+    /// ```
+    fn materialize<C: FromMask<'a, Self::Block>>(self) -> C {
+        C::from_mask(self)
+    }
+
+    /// Consumes steps and builds a bit container in the universe of `[0, len)`.
+    ///
+    /// Steps don't know the universe of their mask, so `materialize` ends a container
+    /// at its last step. `materialize_in` clips steps to `len`, and sizes a container to `len`.
+    ///
+    /// ```
+    /// use compacts::{bits::Mask, ops::{Bits, BitsMut}, BitMap};
+    /// let mut a = BitMap::<[u64; 1024]>::none(200000);
+    /// let mut b = BitMap::<[u64; 1024]>::none(200000);
+    /// a.put1(10);
+    /// b.put1(10);
+    ///
+    /// let map = a.and(&b).materialize_in::<BitMap<[u64; 1024]>>(200000);
+    /// assert_eq!(map.size(), 200000);
+    /// assert_eq!(map.rank1(..200000), 1);
+    /// assert_eq!(a.and(&b).materialize_in::<Vec<u64>>(200000).size(), 200000);
+    /// ```
+    fn materialize_in<C: FromMask<'a, Self::Block>>(self, len: usize) -> C {
+        C::from_mask_in(self, len)
+    }
+
+    /// Counts the occurrences of `1`, without building a container.
+    ///
+    /// ```
+    /// use compacts::{bits::Mask, ops::BitsMut, BitMap};
+    /// let mut a = BitMap::<[u64; 1024]>::none(200000);
+    /// a.put1(10);
+    /// a.put1(150000);
+    /// assert_eq!(a.count_ones(), 2);
+    /// assert_eq!(a.not::<[u64; 1024]>(200000).count_ones(), 200000 - 2);
+    /// assert_eq!(a.within(100..).count_ones(), 1);
+    /// assert!(!a.within(100..).is_empty());
+    /// assert!(a.within(200..100000).is_empty());
+    /// assert_eq!(a.within(100..).first1(), Some(150000));
+    /// ```
+    fn count_ones<W: Word>(self) -> usize
+    where
+        Self: Mask<'a, Block = [W]>,
+    {
+        self.into_steps().map(|(_, block)| block.count1()).sum()
+    }
+
+    /// Returns true if no bits are enabled. Stops at the first enabled bit.
+    #[allow(clippy::wrong_self_convention)]
+    fn is_empty<W: Word>(self) -> bool
+    where
+        Self: Mask<'a, Block = [W]>,
+    {
+        self.into_steps().all(|(_, block)| !block.any())
+    }
+
+    /// Returns the position of the first `1`. Stops at the first enabled bit.
+    fn first1<W: Word>(self) -> Option<usize>
+    where
+        Self: Mask<'a, Block = [W]>,
+    {
+        self.into_steps()
+            .find_map(|(index, block)| block.select1(0).map(|o| index * block.len() * W::BITS + o))
+    }
+}
+
+/// Conversion from a `Mask`.
+///
+/// The steps of a `Mask` must be sorted by its index, and the index of a step is counted
+/// by the length of the block. All implementations build a container in a single pass.
+pub trait FromMask<'a, B: 'a + ?Sized + ToOwned>: Sized {
+    /// Builds a bit container from the steps of `mask`, that ends at the last step.
+    fn from_mask<M: Mask<'a, Block = B>>(mask: M) -> Self;
+
+    /// Builds a bit container of `len` bits from the steps of `mask`,
+    /// steps are clipped to `len`.
+    fn from_mask_in<M: Mask<'a, Block = B>>(mask: M, len: usize) -> Self;
 }
 
 // upstream crates may add new impl of trait `std::iter::Iterator` for type `&[_]` in future versions
@@ -189,6 +358,113 @@ where
     }
 }
 
+macro_rules! implTerminals {
+    ( $( ($name:ident, |$x:ident, $y:ident| $op:expr, $lhs_only:expr, $rhs_only:expr) ),* ) => ($(
+        impl<'a, W, L, R> $name<'a, L, R>
+        where
+            W: Word,
+            L: Mask<'a, Block = [W]>,
+            R: Mask<'a, Block = [W]>,
+        {
+            /// Counts the occurrences of `1`, without allocating blocks.
+            pub fn count_ones(mut self) -> usize {
+                let mut count = 0;
+                while let Some((_, x, y)) = self.next_pair() {
+                    count += words(&x, &y, |$x, $y| $op).map(|w| w.count1()).sum::<usize>();
+                }
+                count
+            }
+
+            /// Returns true if no bits are enabled. Stops at the first enabled bit.
+            pub fn is_empty(mut self) -> bool {
+                while let Some((_, x, y)) = self.next_pair() {
+                    if words(&x, &y, |$x, $y| $op).any(|w| w.any()) {
+                        return false;
+                    }
+                }
+                true
+            }
+
+            /// Returns the position of the first `1`. Stops at the first enabled bit.
+            pub fn first1(mut self) -> Option<usize> {
+                while let Some((index, x, y)) = self.next_pair() {
+                    let len = block_len(&x, &y);
+                    let found = words(&x, &y, |$x, $y| $op).enumerate().find(|(_, w)| w.any());
+                    if let Some((k, w)) = found {
+                        return w.select1(0).map(|o| (index * len + k) * W::BITS + o);
+                    }
+                }
+                None
+            }
+
+            // Aligns blocks of both sides by index, but doesn't combine them.
+            #[allow(clippy::type_complexity)]
+            fn next_pair(&mut self) -> Option<(usize, Option<Cow<'a, [W]>>, Option<Cow<'a, [W]>>)> {
+                loop {
+                    let lhs = &mut self.lhs;
+                    let rhs = &mut self.rhs;
+                    if (!$rhs_only && lhs.peek().is_none()) || (!$lhs_only && rhs.peek().is_none()) {
+                        return None;
+                    }
+
+                    let pair = match cmp_index(lhs.peek(), rhs.peek(), Greater, Less) {
+                        Less => lhs.next().map(|(i, x)| (i, Some(x), None)),
+                        Equal => {
+                            let (i, x) = lhs.next().expect("unreachable");
+                            let (j, y) = rhs.next().expect("unreachable");
+                            debug_assert_eq!(i, j);
+                            Some((i, Some(x), Some(y)))
+                        }
+                        Greater => rhs.next().map(|(j, y)| (j, None, Some(y))),
+                    }?;
+
+                    match pair {
+                        (_, Some(_), None) if !$lhs_only => continue,
+                        (_, None, Some(_)) if !$rhs_only => continue,
+                        _ => return Some(pair),
+                    }
+                }
+            }
+        }
+    )*)
+}
+implTerminals!(
+    (And, |x, y| x & y, false, false),
+    (Or, |x, y| x | y, true, true),
+    (AndNot, |x, y| x & !y, true, false),
+    (Xor, |x, y| x ^ y, true, true)
+);
+
+// Combines words of blocks on the fly, a missing block is regarded as zeros.
+fn words<'r, W, F>(
+    x: &'r Option<Cow<'_, [W]>>,
+    y: &'r Option<Cow<'_, [W]>>,
+    f: F,
+) -> impl Iterator<Item = W> + 'r
+where
+    W: Word,
+    F: 'r + Fn(W, W) -> W,
+{
+    let len = block_len(x, y);
+    let x = x.as_ref().map_or(&[][..], |x| x.as_ref());
+    let y = y.as_ref().map_or(&[][..], |y| y.as_ref());
+    debug_assert!(x.is_empty() || y.is_empty() || x.len() == y.len());
+    (0..len).map(move |k| {
+        let v = x.get(k).copied().unwrap_or(W::NONE);
+        let w = y.get(k).copied().unwrap_or(W::NONE);
+        f(v, w)
+    })
+}
+
+fn block_len<W>(x: &Option<Cow<'_, [W]>>, y: &Option<Cow<'_, [W]>>) -> usize
+where
+    [W]: ToOwned,
+{
+    let x = x.as_ref().map_or(0, |x| x.len());
+    let y = y.as_ref().map_or(0, |y| y.len());
+    std::cmp::max(x, y)
+}
+
 /// The bitwise intersection.
 #[inline]
 pub fn and<'a, L: Mask<'a>, R: Mask<'a>>(lhs: L, rhs: R) -> And<'a, L, R> {
@@ -213,6 +489,120 @@ pub fn xor<'a, L: Mask<'a>, R: Mask<'a>>(lhs: L, rhs: R) -> Xor<'a, L, R> {
     Xor::new(lhs, rhs)
 }
 
+/// An iterator that complements steps in the universe of `[0, len)`.
+#[must_use = "do nothing unless consumed"]
+pub struct Not<'a, M: Mask<'a>, T> {
+    steps: Peekable<M::Steps>,
+    index: usize,
+    len: usize,
+    _words: PhantomData<T>,
+}
+
+impl<'a, M: Mask<'a>, T: Words> Not<'a, M, T> {
+    pub(crate) fn new(mask: M, len: usize) -> Self {
+        Not {
+            steps: mask.into_steps().peekable(),
+            index: 0,
+            len,
+            _words: PhantomData,
+        }
+    }
+}
+
+impl<'a, M, T> Iterator for Not<'a, M, T>
+where
+    T: Words,
+    M: Mask<'a, Block = [T::Word]>,
+{
+    type Item = (usize, Cow<'a, [T::Word]>);
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.index;
+        if index >= blocks_by(self.len, T::BITS) {
+            return None;
+        }
+        self.index += 1;
+
+        let mut block = Vec::new();
+        while let Some(&(i, _)) = self.steps.peek() {
+            if i > index {
+                break;
+            }
+            let (_, step) = self.steps.next().expect("unreachable");
+            assert_eq!(step.len(), T::LEN, "`not` needs the block type of the mask");
+            if i == index {
+                block = step.iter().map(|&w| !w).collect();
+            }
+        }
+        block.resize(T::LEN, <T::Word as Int>::FULL);
+
+        // the last block may exceed the universe.
+        clip(&mut block, index * T::BITS, 0, self.len);
+        Some((index, Cow::Owned(block)))
+    }
+}
+
+/// An iterator that clips steps to `[start, end)`.
+#[must_use = "do nothing unless consumed"]
+pub struct Within<'a, M: Mask<'a>> {
+    steps: M::Steps,
+    start: usize,
+    end: usize,
+}
+
+impl<'a, M: Mask<'a>> Within<'a, M> {
+    pub(crate) fn new<R: RangeBounds<usize>>(mask: M, range: R) -> Self {
+        let (start, end) = to_exclusive(&range, usize::MAX).expect("invalid range");
+        Within {
+            steps: mask.into_steps(),
+            start,
+            end,
+        }
+    }
+}
+
+impl<'a, W, M> Iterator for Within<'a, M>
+where
+    W: Word,
+    M: Mask<'a, Block = [W]>,
+{
+    type Item = (usize, Cow<'a, [W]>);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (index, block) = self.steps.next()?;
+            let bits = block.len() * W::BITS;
+            let lo = index * bits;
+            let hi = lo + bits;
+            if hi <= self.start {
+                continue;
+            }
+            if lo >= self.end {
+                return None;
+            }
+            if self.start <= lo && hi <= self.end {
+                return Some((index, block));
+            }
+            let mut block = block.into_owned();
+            clip(&mut block, lo, self.start, self.end);
+            return Some((index, Cow::Owned(block)));
+        }
+    }
+}
+
+// Disables bits out of `[start, end)`, where `offset` is the position of the first bit of `block`.
+fn clip<W: Word>(block: &mut [W], offset: usize, start: usize, end: usize) {
+    for (k, w) in block.iter_mut().enumerate() {
+        let lo = offset + k * W::BITS;
+        let hi = lo + W::BITS;
+        let i = cmp::min(cmp::max(start, lo), hi) - lo;
+        let j = cmp::min(cmp::max(end, lo), hi) - lo;
+        if i >= j {
+            *w = W::NONE;
+        } else {
+            *w &= num::mask1::<W>(j) & !num::mask1::<W>(i);
+        }
+    }
+}
+
 /// The bitwise in-place intersection.
 pub trait Intersection<T: ?Sized> {
     /// Performs in-place intersection.
@@ -251,61 +641,410 @@ fn cmp_index<T>(
 }
 
 /// `Fold` is an iterator built from `Mask`s.
-pub struct Fold<'a, T>(Box<dyn Iterator<Item = (usize, T)> + 'a>);
+///
+/// Blocks of inputs are aligned by their indices, and kept borrowed until they are combined.
+/// Iterating a `Fold` combines each group of aligned blocks once,
+/// and `count_ones`, `is_empty` and `first1` combine words of borrowed blocks without building blocks.
+pub struct Fold<'a, T> {
+    groups: Box<dyn Iterator<Item = (usize, Vec<T>)> + 'a>,
+    op: Op,
+    combine: fn(Op, Vec<T>) -> T,
+}
+
+/// How a group of aligned blocks is combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    And,
+    Or,
+    AndNot,
+    Xor,
+    Threshold(usize),
+}
+
+impl<'a, T> Fold<'a, T> {
+    pub(crate) fn new<I: 'a + Iterator<Item = (usize, T)>>(iter: I) -> Self {
+        Fold {
+            groups: Box::new(iter.map(|(index, block)| (index, vec![block]))),
+            op: Op::Or,
+            combine: |_, mut group| group.pop().expect("a group of one block"),
+        }
+    }
+}
 
 impl<'a, T: ?Sized> Fold<'a, Cow<'a, T>>
 where
     T: 'a + ToOwned,
 {
-    pub(crate) fn fold<A, B, F>(xs: impl IntoIterator<Item = A>, mut f: F) -> Fold<'a, Cow<'a, T>>
-    where
-        A: 'a + Mask<'a, Block = T>,
-        B: 'a + Mask<'a, Block = T>,
-        F: FnMut(Box<dyn Iterator<Item = (usize, Cow<'a, T>)> + 'a>, A) -> B,
-    {
-        let mut xs = xs.into_iter();
-        if let Some(head) = xs.next() {
-            let init = Box::new(head.into_steps());
-            Fold(xs.fold(init, |a, x| Box::new(f(a, x).into_steps())))
-        } else {
-            Fold(Box::new(empty()))
-        }
-    }
-
     /// Folds `xs` into a single iterator that applies `and` to each bits.
+    ///
+    /// Blocks are aligned by leapfrogging over `xs`, and each block is combined once across all inputs.
+    ///
+    /// A lagging input seeks to the largest index of the others by `Mask::seek`,
+    /// so inputs such as `&BitMap` jump over blocks that can't be in the intersection.
     pub fn and<A>(xs: impl IntoIterator<Item = A>) -> Self
     where
-        A: Mask<'a, Block = T>,
-        And<'a, Box<dyn Iterator<Item = (usize, Cow<'a, T>)> + 'a>, A>: 'a + Mask<'a, Block = T>,
+        A: 'a + Mask<'a, Block = T>,
+        T::Owned: Intersection<T>,
     {
-        Self::fold(xs, And::new)
+        let mut steps = xs.into_iter().map(Mask::into_steps).collect::<Vec<_>>();
+        let heads = steps.iter_mut().map(Iterator::next).collect();
+        Fold {
+            groups: Box::new(Leapfrog {
+                steps,
+                heads,
+                seek: A::seek,
+            }),
+            op: Op::And,
+            combine: |_, group| reduce(group, |acc, block| acc.intersection(block)),
+        }
     }
 
     /// Folds `xs` into a single iterator that applies `or` to each bits.
+    ///
+    /// Blocks are merged by a min-heap over indices, and each block is combined once across all inputs.
     pub fn or<A>(xs: impl IntoIterator<Item = A>) -> Self
     where
-        A: Mask<'a, Block = T>,
-        Or<'a, Box<dyn Iterator<Item = (usize, Cow<'a, T>)> + 'a>, A>: 'a + Mask<'a, Block = T>,
+        A: 'a + Mask<'a, Block = T>,
+        T::Owned: Union<T>,
     {
-        Self::fold(xs, Or::new)
+        Fold {
+            groups: Box::new(Heads::new(xs)),
+            op: Op::Or,
+            combine: |_, group| reduce(group, |acc, block| acc.union(block)),
+        }
     }
 
     /// Folds `xs` into a single iterator that applies `and_not` to each bits.
+    ///
+    /// Blocks of the first mask are subtracted by the blocks of the rest at once.
     pub fn and_not<A>(xs: impl IntoIterator<Item = A>) -> Self
     where
-        A: Mask<'a, Block = T>,
-        AndNot<'a, Box<dyn Iterator<Item = (usize, Cow<'a, T>)> + 'a>, A>: 'a + Mask<'a, Block = T>,
+        A: 'a + Mask<'a, Block = T>,
+        T::Owned: Difference<T>,
     {
-        Self::fold(xs, AndNot::new)
+        let mut xs = xs.into_iter();
+        let groups: Box<dyn Iterator<Item = _>> = if let Some(head) = xs.next() {
+            Box::new(Subtract {
+                head: head.into_steps(),
+                rest: Heads::new(xs),
+            })
+        } else {
+            Box::new(empty())
+        };
+        Fold {
+            groups,
+            op: Op::AndNot,
+            combine: |_, group| reduce(group, |acc, block| acc.difference(block)),
+        }
     }
 
     /// Folds `xs` into a single iterator that applies `xor` to each bits.
+    ///
+    /// Blocks are merged by a min-heap over indices, and each block is combined once across all inputs.
     pub fn xor<A>(xs: impl IntoIterator<Item = A>) -> Self
     where
-        A: Mask<'a, Block = T>,
-        Xor<'a, Box<dyn Iterator<Item = (usize, Cow<'a, T>)> + 'a>, A>: 'a + Mask<'a, Block = T>,
+        A: 'a + Mask<'a, Block = T>,
+        T::Owned: SymmetricDifference<T>,
+    {
+        Fold {
+            groups: Box::new(Heads::new(xs)),
+            op: Op::Xor,
+            combine: |_, group| reduce(group, |acc, block| acc.symmetric_difference(block)),
+        }
+    }
+}
+
+/// Applies `op` to the first block of `group` with each of the rest.
+/// The first block is copied only if `group` has more than one block.
+fn reduce<'a, T, F>(group: Vec<Cow<'a, T>>, op: F) -> Cow<'a, T>
+where
+    T: 'a + ?Sized + ToOwned,
+    F: Fn(&mut T::Owned, &T),
+{
+    let mut group = group.into_iter();
+    let mut acc = group.next().expect("a group has at least one block");
+    for block in group {
+        op(acc.to_mut(), &block);
+    }
+    acc
+}
+
+/// Intersects steps, skipping indices that are missing in any of steps.
+///
+/// Each head seeks to the largest index of heads, until all heads have the same index.
+#[allow(clippy::type_complexity)]
+struct Leapfrog<'a, T: ?Sized + ToOwned, S> {
+    steps: Vec<S>,
+    heads: Vec<Option<(usize, Cow<'a, T>)>>,
+    seek: fn(&mut S, usize) -> Option<(usize, Cow<'a, T>)>,
+}
+
+impl<'a, T, S> Iterator for Leapfrog<'a, T, S>
+where
+    T: 'a + ?Sized + ToOwned,
+    S: Iterator<Item = (usize, Cow<'a, T>)>,
+{
+    type Item = (usize, Vec<Cow<'a, T>>);
+    fn next(&mut self) -> Option<Self::Item> {
+        'leap: loop {
+            let mut max = 0;
+            for head in &self.heads {
+                max = cmp::max(max, head.as_ref()?.0);
+            }
+
+            for (steps, head) in self.steps.iter_mut().zip(&mut self.heads) {
+                if head.as_ref()?.0 < max {
+                    *head = (self.seek)(steps, max);
+                }
+                if head.as_ref()?.0 > max {
+                    continue 'leap;
+                }
+            }
+
+            let mut group = Vec::with_capacity(self.steps.len());
+            for (steps, head) in self.steps.iter_mut().zip(&mut self.heads) {
+                let (_, block) = std::mem::replace(head, steps.next()).expect("aligned");
+                group.push(block);
+            }
+            if group.is_empty() {
+                return None;
+            }
+            return Some((max, group));
+        }
+    }
+}
+
+/// A min-heap over the current block of each steps.
+/// As an iterator, `Heads` yields blocks that have the same index together.
+struct Heads<'a, T: ?Sized + ToOwned, S> {
+    steps: Vec<S>,
+    heads: Vec<Option<Cow<'a, T>>>,
+    heap: BinaryHeap<Reverse<(usize, usize)>>, // (index, which steps)
+}
+
+impl<'a, T, S> Heads<'a, T, S>
+where
+    T: 'a + ?Sized + ToOwned,
+    S: Iterator<Item = (usize, Cow<'a, T>)>,
+{
+    fn new<A>(xs: impl IntoIterator<Item = A>) -> Self
+    where
+        A: Mask<'a, Block = T, Steps = S>,
+    {
+        let steps = xs.into_iter().map(Mask::into_steps).collect::<Vec<_>>();
+        let mut heads = Heads {
+            heads: steps.iter().map(|_| None).collect(),
+            heap: BinaryHeap::with_capacity(steps.len()),
+            steps,
+        };
+        for k in 0..heads.steps.len() {
+            heads.pull(k);
+        }
+        heads
+    }
+
+    fn pull(&mut self, k: usize) {
+        if let Some((index, block)) = self.steps[k].next() {
+            self.heads[k] = Some(block);
+            self.heap.push(Reverse((index, k)));
+        }
+    }
+
+    /// Returns the smallest index of heads.
+    fn peek(&self) -> Option<usize> {
+        self.heap.peek().map(|&Reverse((index, _))| index)
+    }
+
+    /// Pops the block that has the smallest index, and advances its steps.
+    fn pop(&mut self) -> Option<(usize, Cow<'a, T>)> {
+        let Reverse((index, k)) = self.heap.pop()?;
+        let block = self.heads[k].take().expect("pushed with a block");
+        self.pull(k);
+        Some((index, block))
+    }
+
+    /// Skips blocks less than `index`, and pushes blocks at `index` to `group`.
+    fn take_at(&mut self, index: usize, group: &mut Vec<Cow<'a, T>>) {
+        while let Some(i) = self.peek() {
+            if i > index {
+                break;
+            }
+            let (_, block) = self.pop().expect("peeked");
+            if i == index {
+                group.push(block);
+            }
+        }
+    }
+}
+
+impl<'a, T, S> Iterator for Heads<'a, T, S>
+where
+    T: 'a + ?Sized + ToOwned,
+    S: Iterator<Item = (usize, Cow<'a, T>)>,
+{
+    type Item = (usize, Vec<Cow<'a, T>>);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, block) = self.pop()?;
+        let mut group = vec![block];
+        self.take_at(index, &mut group);
+        Some((index, group))
+    }
+}
+
+/// Groups each block of `head` with blocks of `rest` at the same index.
+struct Subtract<'a, T: ?Sized + ToOwned, H, S> {
+    head: H,
+    rest: Heads<'a, T, S>,
+}
+
+impl<'a, T, H, S> Iterator for Subtract<'a, T, H, S>
+where
+    T: 'a + ?Sized + ToOwned,
+    H: Iterator<Item = (usize, Cow<'a, T>)>,
+    S: Iterator<Item = (usize, Cow<'a, T>)>,
+{
+    type Item = (usize, Vec<Cow<'a, T>>);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (index, block) = self.head.next()?;
+        let mut group = vec![block];
+        self.rest.take_at(index, &mut group);
+        Some((index, group))
+    }
+}
+
+/// Combines the `i`-th words of `group` by `op`.
+fn word<W: Word>(op: Op, group: &[Cow<'_, [W]>], i: usize) -> W {
+    let mut words = group
+        .iter()
+        .map(|block| block.get(i).cloned().unwrap_or(W::NONE));
+    match op {
+        Op::And => words.fold(W::FULL, |acc, w| acc & w),
+        Op::Or => words.fold(W::NONE, |acc, w| acc | w),
+        Op::Xor => words.fold(W::NONE, |acc, w| acc ^ w),
+        Op::AndNot => {
+            let head = words.next().unwrap_or(W::NONE);
+            head & !words.fold(W::NONE, |acc, w| acc | w)
+        }
+        Op::Threshold(k) => at_least(k, group.len(), words),
+    }
+}
+
+/// Counts bits of `n` words by bit-sliced counters, and returns the word that has `1` where
+/// the counter is greater than or equal to `k`.
+fn at_least<W: Word>(k: usize, n: usize, words: impl Iterator<Item = W>) -> W {
+    // number of bits to represent `n`, at most 64 planes.
+    let width = 64 - (n as u64).leading_zeros() as usize;
+    let mut planes = [W::NONE; 64];
+    let planes = &mut planes[..width];
+
+    // ripple-carry addition of a word to the counters.
+    for mut carry in words {
+        for p in planes.iter_mut() {
+            if carry == W::NONE {
+                break;
+            }
+            let c = *p & carry;
+            *p ^= carry;
+            carry = c;
+        }
+    }
+
+    // compare counters with `k` from the most significant plane.
+    let mut gt = W::NONE;
+    let mut eq = W::FULL;
+    for (b, &p) in planes.iter().enumerate().rev() {
+        if k & (1 << b) != 0 {
+            eq &= p;
+        } else {
+            gt |= eq & p;
+            eq &= !p;
+        }
+    }
+    gt | eq
+}
+
+/// The number of words of the largest block in `group`.
+fn group_len<W>(group: &[Cow<'_, [W]>]) -> usize
+where
+    [W]: ToOwned,
+{
+    group.iter().map(|block| block.len()).max().unwrap_or(0)
+}
+
+impl<'a, W: Word> Fold<'a, Cow<'a, [W]>> {
+    /// Folds `xs` into a single iterator that yields bits enabled in at least `k` of `xs`.
+    ///
+    /// Bits are counted by bit-sliced counters in each block, without expanding blocks to positions.
+    /// `threshold(1, xs)` is equivalent to `or(xs)`, and `threshold(xs.len(), xs)` is to `and(xs)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero.
+    ///
+    /// ```
+    /// use compacts::{bits::Fold, ops::{Bits, BitsMut}, BitMap};
+    /// let mut a = BitMap::<[u64; 1024]>::none(100000);
+    /// let mut b = BitMap::<[u64; 1024]>::none(100000);
+    /// let mut c = BitMap::<[u64; 1024]>::none(100000);
+    /// a.put1(1);
+    /// a.put1(2);
+    /// b.put1(2);
+    /// b.put1(70000);
+    /// c.put1(2);
+    /// c.put1(70000);
+    /// c.put1(80000);
+    ///
+    /// let fold = Fold::threshold(2, vec![&a, &b, &c]).collect::<BitMap<[u64; 1024]>>();
+    /// assert_eq!(fold.count1(), 2);
+    /// assert!(fold.bit(2) && fold.bit(70000));
+    /// assert_eq!(Fold::threshold(3, vec![&a, &b, &c]).count_ones(), 1);
+    /// assert!(Fold::threshold(4, vec![&a, &b, &c]).is_empty());
+    /// ```
+    pub fn threshold<A>(k: usize, xs: impl IntoIterator<Item = A>) -> Self
+    where
+        A: 'a + Mask<'a, Block = [W]>,
     {
-        Self::fold(xs, Xor::new)
+        assert!(k > 0, "threshold must be greater than zero");
+        let groups = Heads::new(xs).filter(move |(_, group)| group.len() >= k);
+        Fold {
+            groups: Box::new(groups),
+            op: Op::Threshold(k),
+            combine: |op, group| {
+                let words = (0..group_len(&group)).map(|i| word(op, &group, i));
+                Cow::Owned(words.collect())
+            },
+        }
+    }
+
+    /// Counts the occurrences of `1`, without building blocks.
+    pub fn count_ones(self) -> usize {
+        let op = self.op;
+        self.groups
+            .map(|(_, group)| {
+                (0..group_len(&group))
+                    .map(|i| word(op, &group, i).count1())
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Returns true if no bits are enabled. Stops at the first enabled bit.
+    pub fn is_empty(mut self) -> bool {
+        let op = self.op;
+        self.groups
+            .all(|(_, group)| (0..group_len(&group)).all(|i| !word(op, &group, i).any()))
+    }
+
+    /// Returns the position of the first `1`. Stops at the first enabled bit.
+    pub fn first1(mut self) -> Option<usize> {
+        let op = self.op;
+        self.groups.find_map(|(index, group)| {
+            let len = group_len(&group);
+            (0..len).find_map(|i| {
+                let w = word(op, &group, i);
+                w.select1(0).map(|o| (index * len + i) * W::BITS + o)
+            })
+        })
     }
 }
 
@@ -313,10 +1052,11 @@ impl<'a, T: 'a + ?Sized + ToOwned> Iterator for Fold<'a, Cow<'a, T>> {
     type Item = (usize, Cow<'a, T>);
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next()
+        let (index, group) = self.groups.next()?;
+        Some((index, (self.combine)(self.op, group)))
     }
     #[inline]
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.0.size_hint()
+        self.groups.size_hint()
     }
 }
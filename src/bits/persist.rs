@@ -0,0 +1,103 @@
+//! `persist` defines a binary format of immutable bit sequences.
+//!
+//! Every integer is written in little endian, and a sequence is prefixed by its length in `u64`.
+
+use std::io::{self, Read, Write};
+
+use crate::num::{cast, Word};
+
+/// `Persist` writes a value with its samples, and reads it back without rebuilding.
+///
+/// Reading validates samples against the bits, and returns `InvalidData` if they don't match.
+/// `BitMap` has no samples, it rebuilds the prefix sums of its blocks in reading.
+pub trait Persist: Sized {
+    /// Identifies the type and its word width, such as `BitArray<u64>`.
+    fn tag() -> String;
+
+    /// Writes `self` to `w`.
+    fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()>;
+
+    /// Reads a value that `write_to` wrote.
+    fn read_from<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+pub(crate) fn invalid<E>(error: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+pub(crate) fn write_u64<W: Write>(w: &mut W, n: u64) -> io::Result<()> {
+    w.write_all(&n.to_le_bytes())
+}
+
+pub(crate) fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn write_usize<W: Write>(w: &mut W, n: usize) -> io::Result<()> {
+    write_u64(w, cast(n))
+}
+
+pub(crate) fn read_usize<R: Read>(r: &mut R) -> io::Result<usize> {
+    let n = read_u64(r)?;
+    std::convert::TryFrom::try_from(n).map_err(|_| invalid("integer overflows usize"))
+}
+
+pub(crate) fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_usize(w, bytes.len())?;
+    w.write_all(bytes)
+}
+
+pub(crate) fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_usize(r)?;
+    let mut buf = Vec::new();
+    // reads through `take`, so that a corrupted length doesn't allocate all of it.
+    r.take(cast(len)).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+/// Writes words in little endian.
+pub(crate) fn write_words<W: Write, T: Word>(w: &mut W, words: &[T]) -> io::Result<()> {
+    write_usize(w, words.len())?;
+    let bytes = T::BITS / 8;
+    let mut buf = Vec::with_capacity(CHUNK * bytes);
+    for chunk in words.chunks(CHUNK) {
+        buf.clear();
+        for word in chunk {
+            let word = word.getn::<u128>(0, T::BITS);
+            buf.extend_from_slice(&word.to_le_bytes()[..bytes]);
+        }
+        w.write_all(&buf)?;
+    }
+    Ok(())
+}
+
+/// Reads words in little endian.
+pub(crate) fn read_words<R: Read, T: Word>(r: &mut R) -> io::Result<Vec<T>> {
+    let len = read_usize(r)?;
+    let bytes = T::BITS / 8;
+    let mut words = Vec::new();
+    let mut buf = vec![0; CHUNK * bytes];
+    let mut rest = len;
+    while rest > 0 {
+        let n = std::cmp::min(rest, CHUNK);
+        r.read_exact(&mut buf[..n * bytes])?;
+        words.extend(buf[..n * bytes].chunks_exact(bytes).map(|b| {
+            let mut word = [0; 16];
+            word[..bytes].copy_from_slice(b);
+            cast::<u128, T>(u128::from_le_bytes(word))
+        }));
+        rest -= n;
+    }
+    Ok(words)
+}
+
+/// The number of words that are converted at once.
+const CHUNK: usize = 1024;
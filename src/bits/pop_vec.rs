@@ -7,7 +7,12 @@ use std::{
 
 use Ordering::{Equal as EQ, Greater as GT, Less as LT};
 
-use crate::{bits, fenwick::FenwickTree, num, num::Int, ops::*};
+use crate::{
+    bits::{self, Difference, FromMask, Intersection, Mask, SymmetricDifference, Union},
+    fenwick::FenwickTree,
+    num::{self, Int, Word},
+    ops::*,
+};
 
 const UPPER_BLOCK: usize = 1 << 32;
 const SUPER_BLOCK: usize = 2048;
@@ -223,6 +228,48 @@ impl<T: FixedBits> BitsMut for Pop<T> {
     }
 }
 
+impl<T: FixedBits> From<Vec<T>> for Pop<T> {
+    fn from(bits: Vec<T>) -> Self {
+        let samples = Samples::build(&bits);
+        Pop { samples, bits }
+    }
+}
+
+impl<'a, T: Word> FromMask<'a, [T]> for Pop<T> {
+    fn from_mask<M: Mask<'a, Block = [T]>>(mask: M) -> Self {
+        Pop::from(Vec::from_mask(mask))
+    }
+
+    fn from_mask_in<M: Mask<'a, Block = [T]>>(mask: M, len: usize) -> Self {
+        Pop::from(Vec::from_mask_in(mask, len))
+    }
+}
+
+impl<T> AsRef<[T]> for Pop<T> {
+    #[inline]
+    fn as_ref(&self) -> &[T] {
+        &self.bits
+    }
+}
+
+macro_rules! implSetOps {
+    ( $( ($Trait:ident, $method:ident) ),* ) => ($(
+        impl<T: Word, A: ?Sized + AsRef<[T]>> $Trait<A> for Pop<T> {
+            /// Performs the operation on the bits, and rebuilds the samples.
+            fn $method(&mut self, that: &A) {
+                self.bits.$method(that.as_ref());
+                self.samples = Samples::build(&self.bits);
+            }
+        }
+    )*)
+}
+implSetOps!(
+    (Intersection, intersection),
+    (Union, union),
+    (Difference, difference),
+    (SymmetricDifference, symmetric_difference)
+);
+
 impl Samples {
     fn none(len: usize) -> Self {
         let (up, lo, sb) = sampling_blocks(len);
@@ -240,6 +287,18 @@ impl Samples {
         }
     }
 
+    fn build<T: FixedBits>(bits: &[T]) -> Self {
+        let size = bits.size();
+        let mut samples = Samples::none(size);
+        for i in (0..size).step_by(BASIC_BLOCK) {
+            let count1 = bits.rank1(i..std::cmp::min(i + BASIC_BLOCK, size));
+            if count1 > 0 {
+                samples.add(i, num::cast(count1));
+            }
+        }
+        samples
+    }
+
     fn resize(&mut self, bit_len: usize, new_len: usize) {
         let (uppers, lowers, supers) = sampling_blocks(new_len);
 
@@ -0,0 +1,238 @@
+//! `run_vec` defines a run-length encoded bitvector.
+
+use std::{
+    iter::FromIterator,
+    ops::{Range, RangeBounds},
+};
+
+use crate::{
+    bits::{to_exclusive, Difference, Intersection, SymmetricDifference, Union},
+    ops::*,
+};
+
+/// `RunVec` is a run-length encoded bitvector, or a set of disjoint intervals.
+///
+/// Runs of `1` are kept sorted and coalesced, with prefix sums of their lengths for rank/select.
+///
+/// ```
+/// use compacts::{bits::RunVec, ops::{Bits, BitsMut}};
+/// let mut runs = RunVec::none(1000);
+/// runs.insert_range(10..20);
+/// runs.insert_range(30..40);
+/// runs.insert_range(20..25); // coalesced with 10..20
+/// runs.put1(500);
+/// assert_eq!(runs.count1(), 26);
+/// assert_eq!(runs.rank1(..35), 20);
+/// assert_eq!(runs.select1(15), Some(30));
+///
+/// assert_eq!(runs.covering(12), Some(10..25));
+/// assert_eq!(runs.covering(25), None);
+/// runs.remove_range(15..35);
+/// assert_eq!(runs.runs().collect::<Vec<_>>(), vec![10..15, 35..40, 500..501]);
+/// assert_eq!(runs.gaps().collect::<Vec<_>>(), vec![0..10, 15..35, 40..500, 501..1000]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RunVec {
+    runs: Vec<(usize, usize)>, // [start, end)
+    sums: Vec<usize>,          // sums[k] is the number of `1` in runs[..k]
+    len: usize,
+}
+
+impl RunVec {
+    /// Constructs an empty bitvector that has `n` bits.
+    pub fn none(n: usize) -> Self {
+        RunVec {
+            runs: Vec::new(),
+            sums: vec![0],
+            len: n,
+        }
+    }
+
+    /// Returns an iterator over runs of `1`.
+    pub fn runs<'a>(&'a self) -> impl Iterator<Item = Range<usize>> + 'a {
+        self.runs.iter().map(|&(i, j)| i..j)
+    }
+
+    /// Returns an iterator over runs of `0`.
+    pub fn gaps<'a>(&'a self) -> impl Iterator<Item = Range<usize>> + 'a {
+        let ends = std::iter::once(0).chain(self.runs.iter().map(|&(_, j)| j));
+        let starts = self.runs.iter().map(|&(i, _)| i).chain(Some(self.len));
+        ends.zip(starts).filter(|(i, j)| i < j).map(|(i, j)| i..j)
+    }
+
+    /// Returns the run of `1` that covers `i`.
+    pub fn covering(&self, i: usize) -> Option<Range<usize>> {
+        let k = self.runs.partition_point(|&(_, end)| end <= i);
+        self.runs
+            .get(k)
+            .filter(|&&(start, _)| start <= i)
+            .map(|&(start, end)| start..end)
+    }
+
+    /// Enables bits in `range`, coalescing runs that overlap or are adjacent to `range`.
+    pub fn insert_range<R: RangeBounds<usize>>(&mut self, range: R) {
+        let (s, e) = to_exclusive(&range, self.len).expect("out of bounds");
+        if s == e {
+            return;
+        }
+        let lo = self.runs.partition_point(|&(_, end)| end < s);
+        let hi = self.runs.partition_point(|&(start, _)| start <= e);
+        let run = if lo < hi {
+            (
+                std::cmp::min(s, self.runs[lo].0),
+                std::cmp::max(e, self.runs[hi - 1].1),
+            )
+        } else {
+            (s, e)
+        };
+        self.runs.splice(lo..hi, Some(run));
+        self.fix(lo);
+    }
+
+    /// Disables bits in `range`, splitting a run that `range` is inside of.
+    pub fn remove_range<R: RangeBounds<usize>>(&mut self, range: R) {
+        let (s, e) = to_exclusive(&range, self.len).expect("out of bounds");
+        if s == e {
+            return;
+        }
+        let lo = self.runs.partition_point(|&(_, end)| end <= s);
+        let hi = self.runs.partition_point(|&(start, _)| start < e);
+        if lo < hi {
+            let head = self.runs[lo].0;
+            let tail = self.runs[hi - 1].1;
+            let rest = [(head, s), (e, tail)];
+            let rest = rest.iter().cloned().filter(|&(i, j)| i < j);
+            self.runs.splice(lo..hi, rest);
+            self.fix(lo);
+        }
+    }
+
+    // Recomputes prefix sums from `runs[k]`.
+    fn fix(&mut self, k: usize) {
+        self.sums.truncate(k + 1);
+        let mut sum = self.sums[k];
+        for &(i, j) in &self.runs[k..] {
+            sum += j - i;
+            self.sums.push(sum);
+        }
+    }
+
+    // Merges runs of both sides, keeping positions where `f` returns true.
+    fn merge(&self, that: &RunVec, f: fn(bool, bool) -> bool) -> RunVec {
+        let len = std::cmp::max(self.len, that.len);
+        let mut bounds = Vec::with_capacity((self.runs.len() + that.runs.len()) * 2);
+        for &(i, j) in self.runs.iter().chain(&that.runs) {
+            bounds.push(i);
+            bounds.push(j);
+        }
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut out = RunVec::none(len);
+        let (mut x, mut y) = (self.runs.iter().peekable(), that.runs.iter().peekable());
+        for w in bounds.windows(2) {
+            let (i, j) = (w[0], w[1]);
+            while matches!(x.peek(), Some(&&(_, end)) if end <= i) {
+                x.next();
+            }
+            while matches!(y.peek(), Some(&&(_, end)) if end <= i) {
+                y.next();
+            }
+            let a = matches!(x.peek(), Some(&&(start, _)) if start <= i);
+            let b = matches!(y.peek(), Some(&&(start, _)) if start <= i);
+            if f(a, b) {
+                match out.runs.last_mut() {
+                    Some(last) if last.1 == i => last.1 = j,
+                    _ => out.runs.push((i, j)),
+                }
+            }
+        }
+        out.fix(0);
+        out
+    }
+}
+
+impl Bits for RunVec {
+    #[inline]
+    fn size(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn bit(&self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.size());
+        self.covering(i).is_some()
+    }
+
+    #[inline]
+    fn count1(&self) -> usize {
+        self.sums[self.runs.len()]
+    }
+
+    fn rank1<R: RangeBounds<usize>>(&self, range: R) -> usize {
+        let rank = |p: usize| {
+            let k = self.runs.partition_point(|&(start, _)| start < p);
+            match k.checked_sub(1).map(|k| self.runs[k]) {
+                Some((_, end)) if end > p => self.sums[k] - (end - p),
+                _ => self.sums[k],
+            }
+        };
+        match to_exclusive(&range, self.size()).expect("out of bounds") {
+            (0, j) => rank(j),
+            (i, j) => rank(j) - rank(i),
+        }
+    }
+
+    fn select1(&self, n: usize) -> Option<usize> {
+        if n < self.count1() {
+            // the first run such that sums[k + 1] > n
+            let k = self.sums[1..].partition_point(|&sum| sum <= n);
+            Some(self.runs[k].0 + (n - self.sums[k]))
+        } else {
+            None
+        }
+    }
+}
+
+impl BitsMut for RunVec {
+    #[inline]
+    fn put1(&mut self, i: usize) {
+        BOUNDS_CHECK!(i < self.size());
+        self.insert_range(i..=i);
+    }
+
+    #[inline]
+    fn put0(&mut self, i: usize) {
+        BOUNDS_CHECK!(i < self.size());
+        self.remove_range(i..=i);
+    }
+}
+
+impl FromIterator<Range<usize>> for RunVec {
+    /// Builds a bitvector from ranges of `1`, the size is the maximum end of ranges.
+    fn from_iter<I: IntoIterator<Item = Range<usize>>>(iterable: I) -> Self {
+        let ranges = iterable.into_iter().collect::<Vec<_>>();
+        let len = ranges.iter().map(|r| r.end).max().unwrap_or(0);
+        let mut runs = RunVec::none(len);
+        for range in ranges {
+            runs.insert_range(range);
+        }
+        runs
+    }
+}
+
+macro_rules! implSetOps {
+    ( $( ($Op:ident, $fn:ident, |$x:ident, $y:ident| $op:expr) ),* ) => ($(
+        impl $Op<RunVec> for RunVec {
+            fn $fn(&mut self, that: &RunVec) {
+                *self = self.merge(that, |$x, $y| $op);
+            }
+        }
+    )*)
+}
+implSetOps!(
+    (Intersection, intersection, |x, y| x && y),
+    (Union, union, |x, y| x || y),
+    (Difference, difference, |x, y| x && !y),
+    (SymmetricDifference, symmetric_difference, |x, y| x != y)
+);
@@ -0,0 +1,305 @@
+//! `simd` has SIMD kernels for slices of words.
+//!
+//! Words are plain integers, so that kernels see slices as bytes.
+//! Kernels are selected at runtime by `is_x86_feature_detected!`,
+//! and the scalar fallback is used on other platforms.
+
+use crate::num::Word;
+
+pub(crate) fn bytes<T: Word>(slice: &[T]) -> &[u8] {
+    let len = std::mem::size_of_val(slice);
+    // safe because `Word` is implemented only for primitive integers.
+    unsafe { std::slice::from_raw_parts(slice.as_ptr() as *const u8, len) }
+}
+
+pub(crate) fn bytes_mut<T: Word>(slice: &mut [T]) -> &mut [u8] {
+    let len = std::mem::size_of_val(slice);
+    // safe because `Word` is implemented only for primitive integers.
+    unsafe { std::slice::from_raw_parts_mut(slice.as_mut_ptr() as *mut u8, len) }
+}
+
+macro_rules! dispatch {
+    ($fn:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return unsafe { x86::avx2::$fn($($arg),*) };
+            }
+            if is_x86_feature_detected!("sse4.2") && is_x86_feature_detected!("popcnt") {
+                return unsafe { x86::sse42::$fn($($arg),*) };
+            }
+        }
+        scalar::$fn($($arg),*)
+    }};
+}
+
+/// Counts `1` in `data`.
+pub(crate) fn count1(data: &[u8]) -> usize {
+    dispatch!(count1(data))
+}
+
+/// `dst &= src`
+pub(crate) fn and(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(and(dst, src))
+}
+
+/// `dst |= src`
+pub(crate) fn or(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(or(dst, src))
+}
+
+/// `dst &= !src`
+pub(crate) fn and_not(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(and_not(dst, src))
+}
+
+/// `dst ^= src`
+pub(crate) fn xor(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len());
+    dispatch!(xor(dst, src))
+}
+
+mod scalar {
+    pub(crate) fn count1(data: &[u8]) -> usize {
+        let chunks = data.chunks_exact(8);
+        let rest = chunks.remainder();
+        let mut sum = 0;
+        for chunk in chunks {
+            let mut word = [0; 8];
+            word.copy_from_slice(chunk);
+            sum += u64::from_ne_bytes(word).count_ones() as usize;
+        }
+        sum + rest.iter().map(|b| b.count_ones() as usize).sum::<usize>()
+    }
+
+    macro_rules! binop {
+        ($( ($fn:ident, |$a:ident, $b:ident| $op:expr) ),*) => ($(
+            pub(crate) fn $fn(dst: &mut [u8], src: &[u8]) {
+                for ($a, &$b) in dst.iter_mut().zip(src) {
+                    *$a = $op;
+                }
+            }
+        )*)
+    }
+    binop!(
+        (and, |a, b| *a & b),
+        (or, |a, b| *a | b),
+        (and_not, |a, b| *a & !b),
+        (xor, |a, b| *a ^ b)
+    );
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    macro_rules! binop {
+        ($feature:literal, $Vec:ty, $load:ident, $store:ident,
+         $( ($fn:ident, |$a:ident, $b:ident| $op:expr) ),*) => ($(
+            #[target_feature(enable = $feature)]
+            pub(crate) unsafe fn $fn(dst: &mut [u8], src: &[u8]) {
+                const LANES: usize = std::mem::size_of::<$Vec>();
+                let n = dst.len() / LANES * LANES;
+                for i in (0..n).step_by(LANES) {
+                    let $a = $load(dst.as_ptr().add(i) as *const $Vec);
+                    let $b = $load(src.as_ptr().add(i) as *const $Vec);
+                    $store(dst.as_mut_ptr().add(i) as *mut $Vec, $op);
+                }
+                super::super::scalar::$fn(&mut dst[n..], &src[n..]);
+            }
+        )*)
+    }
+
+    pub(crate) mod sse42 {
+        use std::arch::x86_64::*;
+
+        binop!(
+            "sse4.2",
+            __m128i,
+            _mm_loadu_si128,
+            _mm_storeu_si128,
+            (and, |a, b| _mm_and_si128(a, b)),
+            (or, |a, b| _mm_or_si128(a, b)),
+            (and_not, |a, b| _mm_andnot_si128(b, a)),
+            (xor, |a, b| _mm_xor_si128(a, b))
+        );
+
+        #[target_feature(enable = "sse4.2,popcnt")]
+        pub(crate) unsafe fn count1(data: &[u8]) -> usize {
+            let n = data.len() / 8 * 8;
+            let mut sum = 0;
+            for i in (0..n).step_by(8) {
+                let word = std::ptr::read_unaligned(data.as_ptr().add(i) as *const u64);
+                sum += _popcnt64(word as i64) as usize;
+            }
+            sum + super::super::scalar::count1(&data[n..])
+        }
+    }
+
+    pub(crate) mod avx2 {
+        use std::arch::x86_64::*;
+
+        binop!(
+            "avx2",
+            __m256i,
+            _mm256_loadu_si256,
+            _mm256_storeu_si256,
+            (and, |a, b| _mm256_and_si256(a, b)),
+            (or, |a, b| _mm256_or_si256(a, b)),
+            (and_not, |a, b| _mm256_andnot_si256(b, a)),
+            (xor, |a, b| _mm256_xor_si256(a, b))
+        );
+
+        /// Counts `1` of each 64 bits lane by the nibble lookup.
+        #[target_feature(enable = "avx2")]
+        unsafe fn popcount(v: __m256i) -> __m256i {
+            #[rustfmt::skip]
+            let lookup = _mm256_setr_epi8(
+                0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+                0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4,
+            );
+            let mask = _mm256_set1_epi8(0x0f);
+            let lo = _mm256_and_si256(v, mask);
+            let hi = _mm256_and_si256(_mm256_srli_epi16(v, 4), mask);
+            let cnt = _mm256_add_epi8(
+                _mm256_shuffle_epi8(lookup, lo),
+                _mm256_shuffle_epi8(lookup, hi),
+            );
+            _mm256_sad_epu8(cnt, _mm256_setzero_si256())
+        }
+
+        /// Carry save adder, returns `(carry, sum)`.
+        #[inline(always)]
+        unsafe fn csa(a: __m256i, b: __m256i, c: __m256i) -> (__m256i, __m256i) {
+            let u = _mm256_xor_si256(a, b);
+            let h = _mm256_or_si256(_mm256_and_si256(a, b), _mm256_and_si256(u, c));
+            (h, _mm256_xor_si256(u, c))
+        }
+
+        /// Harley-Seal popcount, that counts 16 vectors at once by carry save adders.
+        ///
+        /// See Muła, Kurz and Lemire, "Faster Population Counts Using AVX2 Instructions".
+        #[target_feature(enable = "avx2")]
+        pub(crate) unsafe fn count1(data: &[u8]) -> usize {
+            const LANES: usize = 32;
+            let load =
+                |i: usize| _mm256_loadu_si256(data.as_ptr().add(i * LANES) as *const __m256i);
+            let vecs = data.len() / LANES;
+
+            let mut total = _mm256_setzero_si256();
+            let mut ones = _mm256_setzero_si256();
+            let mut twos = _mm256_setzero_si256();
+            let mut fours = _mm256_setzero_si256();
+            let mut eights = _mm256_setzero_si256();
+
+            let mut i = 0;
+            while i + 16 <= vecs {
+                let (twos_a, o) = csa(ones, load(i), load(i + 1));
+                let (twos_b, o) = csa(o, load(i + 2), load(i + 3));
+                let (fours_a, t) = csa(twos, twos_a, twos_b);
+                let (twos_a, o) = csa(o, load(i + 4), load(i + 5));
+                let (twos_b, o) = csa(o, load(i + 6), load(i + 7));
+                let (fours_b, t) = csa(t, twos_a, twos_b);
+                let (eights_a, f) = csa(fours, fours_a, fours_b);
+                let (twos_a, o) = csa(o, load(i + 8), load(i + 9));
+                let (twos_b, o) = csa(o, load(i + 10), load(i + 11));
+                let (fours_a, t) = csa(t, twos_a, twos_b);
+                let (twos_a, o) = csa(o, load(i + 12), load(i + 13));
+                let (twos_b, o) = csa(o, load(i + 14), load(i + 15));
+                let (fours_b, t) = csa(t, twos_a, twos_b);
+                let (eights_b, f) = csa(f, fours_a, fours_b);
+                let (sixteens, e) = csa(eights, eights_a, eights_b);
+                total = _mm256_add_epi64(total, popcount(sixteens));
+                ones = o;
+                twos = t;
+                fours = f;
+                eights = e;
+                i += 16;
+            }
+
+            total = _mm256_slli_epi64(total, 4);
+            total = _mm256_add_epi64(total, _mm256_slli_epi64(popcount(eights), 3));
+            total = _mm256_add_epi64(total, _mm256_slli_epi64(popcount(fours), 2));
+            total = _mm256_add_epi64(total, _mm256_slli_epi64(popcount(twos), 1));
+            total = _mm256_add_epi64(total, popcount(ones));
+            while i < vecs {
+                total = _mm256_add_epi64(total, popcount(load(i)));
+                i += 1;
+            }
+
+            let mut lanes = [0u64; 4];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+            let sum = lanes.iter().sum::<u64>() as usize;
+            sum + super::super::scalar::count1(&data[vecs * LANES..])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+    use rand::prelude::*;
+
+    // quickcheck generates small numbers, scales them to cover loops over 16 vectors.
+    fn random(len: u16) -> Vec<u8> {
+        let len = len as usize * 61;
+        let mut rng = thread_rng();
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    // Checks every kernel that the host supports against the scalar one.
+    macro_rules! kernels {
+        ($fn:ident, |$k:ident| $check:expr) => {{
+            let $k = scalar::$fn;
+            let mut ok = $check;
+            #[cfg(target_arch = "x86_64")]
+            {
+                if is_x86_feature_detected!("sse4.2") && is_x86_feature_detected!("popcnt") {
+                    let $k = |a: &mut [u8], b: &[u8]| unsafe { x86::sse42::$fn(a, b) };
+                    ok &= $check;
+                }
+                if is_x86_feature_detected!("avx2") {
+                    let $k = |a: &mut [u8], b: &[u8]| unsafe { x86::avx2::$fn(a, b) };
+                    ok &= $check;
+                }
+            }
+            ok
+        }};
+    }
+
+    quickcheck! {
+        fn count1(len: u16, skip: u8) -> bool {
+            let data = random(len);
+            let data = &data[std::cmp::min(skip as usize, data.len())..];
+            let want = data.iter().map(|b| b.count_ones() as usize).sum::<usize>();
+
+            let mut ok = scalar::count1(data) == want && super::count1(data) == want;
+            #[cfg(target_arch = "x86_64")]
+            {
+                if is_x86_feature_detected!("sse4.2") && is_x86_feature_detected!("popcnt") {
+                    ok &= unsafe { x86::sse42::count1(data) } == want;
+                }
+                if is_x86_feature_detected!("avx2") {
+                    ok &= unsafe { x86::avx2::count1(data) } == want;
+                }
+            }
+            ok
+        }
+
+        fn binops(len: u16) -> bool {
+            let (a, b) = (random(len), random(len));
+            let want = |f: fn(u8, u8) -> u8| a.iter().zip(&b).map(|(&x, &y)| f(x, y)).collect::<Vec<_>>();
+            let run = |kernel: &dyn Fn(&mut [u8], &[u8])| {
+                let mut dst = a.clone();
+                kernel(&mut dst, &b);
+                dst
+            };
+            kernels!(and, |k| run(&k) == want(|x, y| x & y))
+                && kernels!(or, |k| run(&k) == want(|x, y| x | y))
+                && kernels!(and_not, |k| run(&k) == want(|x, y| x & !y))
+                && kernels!(xor, |k| run(&k) == want(|x, y| x ^ y))
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! `veb` defines a bitset with a hierarchy of summaries.
+
+use std::ops::RangeBounds;
+
+use crate::{bits::blocks_by, ops::*};
+
+/// `VebSet` is a van Emde Boas style set of integers in `[0, size)`.
+///
+/// `levels[0]` is the bitset itself, and each bit of `levels[k + 1]` marks
+/// whether the corresponding word of `levels[k]` is non-empty.
+/// The top level is a single word, so that `insert`, `remove`, `next1`, `prev1`
+/// visit at most one word per level, O(log64 n).
+///
+/// ```
+/// use compacts::{bits::VebSet, ops::Bits};
+/// let mut set = VebSet::none(1 << 30);
+/// assert!(set.insert(100));
+/// assert!(set.insert(1 << 29));
+/// assert!(!set.insert(100));
+/// assert_eq!(set.count1(), 2);
+///
+/// assert_eq!(set.next1(101), Some(1 << 29));
+/// assert_eq!(set.prev1(1 << 28), Some(100));
+/// assert_eq!(set.min(), Some(100));
+/// assert_eq!(set.max(), Some(1 << 29));
+///
+/// assert_eq!(set.pop_min(), Some(100));
+/// assert_eq!(set.next1(0), Some(1 << 29));
+/// assert!(set.remove(1 << 29));
+/// assert_eq!(set.min(), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VebSet {
+    levels: Vec<Vec<u64>>,
+    ones: usize,
+    len: usize,
+}
+
+const WIDTH: usize = 64;
+
+impl VebSet {
+    /// Constructs an empty set of integers less than `n`.
+    pub fn none(n: usize) -> Self {
+        let mut levels = vec![vec![0; blocks_by(n, WIDTH)]];
+        while levels[levels.len() - 1].len() > 1 {
+            let words = blocks_by(levels[levels.len() - 1].len(), WIDTH);
+            levels.push(vec![0; words]);
+        }
+        VebSet {
+            levels,
+            ones: 0,
+            len: n,
+        }
+    }
+
+    /// Adds `i` to the set, returns false if the set already had `i`.
+    pub fn insert(&mut self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.len);
+        let mut i = i;
+        for (k, level) in self.levels.iter_mut().enumerate() {
+            let (q, r) = divrem!(i, WIDTH);
+            let word = level[q];
+            level[q] |= 1 << r;
+            if k == 0 && word == level[q] {
+                return false;
+            }
+            if word != 0 {
+                break; // upper levels already mark this word
+            }
+            i = q;
+        }
+        self.ones += 1;
+        true
+    }
+
+    /// Removes `i` from the set, returns false if the set didn't have `i`.
+    pub fn remove(&mut self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.len);
+        let mut i = i;
+        for (k, level) in self.levels.iter_mut().enumerate() {
+            let (q, r) = divrem!(i, WIDTH);
+            let word = level[q];
+            level[q] &= !(1 << r);
+            if k == 0 && word == level[q] {
+                return false;
+            }
+            if level[q] != 0 {
+                break; // this word is still non-empty
+            }
+            i = q;
+        }
+        self.ones -= 1;
+        true
+    }
+
+    /// Returns the smallest element that is greater than or equal to `i`.
+    pub fn next1(&self, i: usize) -> Option<usize> {
+        let mut i = i;
+        let mut k = 0;
+        // ascend until a word has an element after `i`
+        loop {
+            let (q, r) = divrem!(i, WIDTH);
+            let word = *self.levels[k].get(q)? & (!0 << r);
+            if word != 0 {
+                i = q * WIDTH + word.trailing_zeros() as usize;
+                break;
+            }
+            k += 1;
+            if k == self.levels.len() {
+                return None;
+            }
+            i = q + 1;
+        }
+        // descend to the first element of the marked word
+        while k > 0 {
+            k -= 1;
+            i = i * WIDTH + self.levels[k][i].trailing_zeros() as usize;
+        }
+        Some(i)
+    }
+
+    /// Returns the largest element that is less than or equal to `i`.
+    pub fn prev1(&self, i: usize) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let mut i = std::cmp::min(i, self.len - 1);
+        let mut k = 0;
+        // ascend until a word has an element before `i`
+        loop {
+            let (q, r) = divrem!(i, WIDTH);
+            let word = self.levels[k][q] & (!0 >> (WIDTH - 1 - r));
+            if word != 0 {
+                i = q * WIDTH + (WIDTH - 1 - word.leading_zeros() as usize);
+                break;
+            }
+            k += 1;
+            if q == 0 || k == self.levels.len() {
+                return None;
+            }
+            i = q - 1;
+        }
+        // descend to the last element of the marked word
+        while k > 0 {
+            k -= 1;
+            let word = self.levels[k][i];
+            i = i * WIDTH + (WIDTH - 1 - word.leading_zeros() as usize);
+        }
+        Some(i)
+    }
+
+    /// Returns the smallest element.
+    pub fn min(&self) -> Option<usize> {
+        self.next1(0)
+    }
+
+    /// Returns the largest element.
+    pub fn max(&self) -> Option<usize> {
+        self.prev1(usize::MAX)
+    }
+
+    /// Removes and returns the smallest element.
+    pub fn pop_min(&mut self) -> Option<usize> {
+        let min = self.min()?;
+        self.remove(min);
+        Some(min)
+    }
+
+    /// Removes and returns the largest element.
+    pub fn pop_max(&mut self) -> Option<usize> {
+        let max = self.max()?;
+        self.remove(max);
+        Some(max)
+    }
+}
+
+impl Bits for VebSet {
+    #[inline]
+    fn size(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    fn bit(&self, i: usize) -> bool {
+        BOUNDS_CHECK!(i < self.size());
+        self.levels[0].bit(i)
+    }
+
+    #[inline]
+    fn count1(&self) -> usize {
+        self.ones
+    }
+
+    #[inline]
+    fn rank1<R: RangeBounds<usize>>(&self, range: R) -> usize {
+        self.levels[0].rank1(range)
+    }
+
+    #[inline]
+    fn select1(&self, n: usize) -> Option<usize> {
+        self.levels[0].select1(n)
+    }
+}
+
+impl BitsMut for VebSet {
+    #[inline]
+    fn put1(&mut self, i: usize) {
+        self.insert(i);
+    }
+
+    #[inline]
+    fn put0(&mut self, i: usize) {
+        self.remove(i);
+    }
+}
@@ -4,7 +4,11 @@
 
 use std::{convert::TryFrom, fmt, hash::Hash, iter::Sum, ops};
 
-use crate::{bits::to_exclusive, num, ops::*};
+use crate::{
+    bits::{simd, to_exclusive},
+    num,
+    ops::*,
+};
 
 /// A trait for integral types.
 pub trait Int:
@@ -106,6 +110,10 @@ macro_rules! implInt {
             const SIZE: usize = Self::BITS;
             #[inline(always)]
             fn none() -> Self { Self::NONE }
+            #[inline]
+            fn count1_slice(slice: &[Self]) -> usize {
+                simd::count1(simd::bytes(slice))
+            }
         }
 
         // impl FixedBits for $Sint {
@@ -234,54 +242,80 @@ trait Broadword {
 }
 
 impl Broadword for u64 {
+    /// Selects by `pdep` if the CPU has BMI2, and by the broadword algorithm otherwise.
+    ///
+    /// Note that `pdep` is microcoded on AMD CPUs before Zen 3,
+    /// and it is much slower than the broadword algorithm on them.
+    #[inline]
     fn broadword(&self, c: usize) -> Option<usize> {
-        const X01: u64 = 0x0101_0101_0101_0101;
-        const X02: u64 = 0x2020_2020_2020_2020;
-        const X33: u64 = 0x3333_3333_3333_3333;
-        const X22: u64 = 0x2222_2222_2222_2222;
-        const X80: u64 = 0x2010_0804_0201_0080;
-        const X81: u64 = 0x2010_0804_0201_0081;
-        const X0F: u64 = 0x0f0f_0f0f_0f0f_0f0f;
-        const X55: u64 = X22 + X33 + X22 + X33;
-        const X8X: u64 = X81 + X80 + X80 + X80;
-
-        #[inline]
-        const fn le8(x: u64, y: u64) -> u64 {
-            let x8 = X02 + X02 + X02 + X02;
-            let xs = (y | x8) - (x & !x8);
-            (xs ^ x ^ y) & x8
-        }
-
-        #[inline]
-        const fn lt8(x: u64, y: u64) -> u64 {
-            let x8 = X02 + X02 + X02 + X02;
-            let xs = (x | x8) - (y & !x8);
-            (xs ^ x ^ !y) & x8
-        }
-
         if c < self.count1() {
-            let x = *self;
-            let c = c as u64;
-            let s0 = x - ((x & X55) >> 1);
-            let s1 = (s0 & X33) + ((s0 >> 2) & X33);
-            let s2 = ((s1 + (s1 >> 4)) & X0F).wrapping_mul(X01);
-            let p0 = (le8(s2, c * X01) >> 7).wrapping_mul(X01);
-            let p1 = (p0 >> 53) & !0x7;
-            let p2 = p1 as u32;
-            let p3 = (s2 << 8).wrapping_shr(p2);
-            let p4 = c - (p3 & 0xFF);
-            let p5 = lt8(0x0, ((x.wrapping_shr(p2) & 0xFF) * X01) & X8X);
-            let s3 = (p5 >> 0x7).wrapping_mul(X01);
-            let p6 = (le8(s3, p4 * X01) >> 7).wrapping_mul(X01) >> 56;
-            let p7 = p1 + p6;
-            // assert!((p7 as usize) < Self::BITS);
-            Some(p7 as usize)
+            #[cfg(target_arch = "x86_64")]
+            {
+                // `is_x86_feature_detected!` caches the result of `cpuid` by itself.
+                if is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("bmi2") {
+                    return Some(unsafe { pdep_select1(*self, c) });
+                }
+            }
+            Some(broadword_select1(*self, c))
         } else {
             None
         }
     }
 }
 
+/// Deposits `1 << c` to the `c`-th enabled bit of `x`, and counts trailing zeros.
+///
+/// `pdep` is microcoded on AMD CPUs before Zen 3, that takes a cycle per bit of the mask.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi1,bmi2")]
+unsafe fn pdep_select1(x: u64, c: usize) -> usize {
+    use std::arch::x86_64::{_pdep_u64, _tzcnt_u64};
+    _tzcnt_u64(_pdep_u64(1 << c, x)) as usize
+}
+
+/// `c` must be less than `x.count1()`.
+fn broadword_select1(x: u64, c: usize) -> usize {
+    const X01: u64 = 0x0101_0101_0101_0101;
+    const X02: u64 = 0x2020_2020_2020_2020;
+    const X33: u64 = 0x3333_3333_3333_3333;
+    const X22: u64 = 0x2222_2222_2222_2222;
+    const X80: u64 = 0x2010_0804_0201_0080;
+    const X81: u64 = 0x2010_0804_0201_0081;
+    const X0F: u64 = 0x0f0f_0f0f_0f0f_0f0f;
+    const X55: u64 = X22 + X33 + X22 + X33;
+    const X8X: u64 = X81 + X80 + X80 + X80;
+
+    #[inline]
+    const fn le8(x: u64, y: u64) -> u64 {
+        let x8 = X02 + X02 + X02 + X02;
+        let xs = (y | x8) - (x & !x8);
+        (xs ^ x ^ y) & x8
+    }
+
+    #[inline]
+    const fn lt8(x: u64, y: u64) -> u64 {
+        let x8 = X02 + X02 + X02 + X02;
+        let xs = (x | x8) - (y & !x8);
+        (xs ^ x ^ !y) & x8
+    }
+
+    let c = c as u64;
+    let s0 = x - ((x & X55) >> 1);
+    let s1 = (s0 & X33) + ((s0 >> 2) & X33);
+    let s2 = ((s1 + (s1 >> 4)) & X0F).wrapping_mul(X01);
+    let p0 = (le8(s2, c * X01) >> 7).wrapping_mul(X01);
+    let p1 = (p0 >> 53) & !0x7;
+    let p2 = p1 as u32;
+    let p3 = (s2 << 8).wrapping_shr(p2);
+    let p4 = c - (p3 & 0xFF);
+    let p5 = lt8(0x0, ((x.wrapping_shr(p2) & 0xFF) * X01) & X8X);
+    let s3 = (p5 >> 0x7).wrapping_mul(X01);
+    let p6 = (le8(s3, p4 * X01) >> 7).wrapping_mul(X01) >> 56;
+    let p7 = p1 + p6;
+    // assert!((p7 as usize) < Self::BITS);
+    p7 as usize
+}
+
 macro_rules! implBroadword {
     ( $( $Ty:ty ),* ) => ($(
         impl Broadword for $Ty {
@@ -308,15 +342,13 @@ impl Broadword for u128 {
     /// assert_eq!(n.select1(4), Some(65));
     /// ```
     fn broadword(&self, c: usize) -> Option<usize> {
-        let slice = [*self as u64, (*self >> 64) as u64];
-        slice.as_ref().select1(c)
-
-        // let (hi, lo) = ((*self >> 64) as u64, *self as u64);
-        // if c < lo.count1() {
-        //     lo.select1(c)
-        // } else {
-        //     hi.select1(c - lo.count1()).map(|x| x + 64)
-        // }
+        // selects in two halves, so that each half takes the BMI2 path if available.
+        let (hi, lo) = ((*self >> 64) as u64, *self as u64);
+        if c < lo.count1() {
+            lo.select1(c)
+        } else {
+            hi.select1(c - lo.count1()).map(|x| x + 64)
+        }
     }
 }
 
@@ -337,3 +369,76 @@ impl Broadword for usize {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::quickcheck;
+    use rand::prelude::*;
+
+    fn naive(x: u128, c: usize) -> Option<usize> {
+        (0..128).filter(|&i| x.bit(i)).nth(c)
+    }
+
+    // Full width words, sparse, dense and edge ones.
+    fn words(rng: &mut ThreadRng) -> Vec<u64> {
+        let mut words = vec![0, !0, 1, 1 << 63, 0x8000_0000_0000_0001];
+        for _ in 0..1000 {
+            let x = rng.gen::<u64>();
+            words.push(x);
+            words.push(x & rng.gen::<u64>() & rng.gen::<u64>());
+            words.push(x | rng.gen::<u64>() | rng.gen::<u64>());
+            words.push(x << rng.gen_range(0, 64));
+        }
+        words
+    }
+
+    #[test]
+    fn select1_u64() {
+        let mut rng = thread_rng();
+        for x in words(&mut rng) {
+            for c in 0..x.count1() {
+                let want = naive(x as u128, c);
+                assert_eq!(x.select1(c), want);
+                assert_eq!(Some(broadword_select1(x, c)), want);
+                #[cfg(target_arch = "x86_64")]
+                {
+                    if is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("bmi2") {
+                        assert_eq!(Some(unsafe { pdep_select1(x, c) }), want);
+                    }
+                }
+            }
+            assert_eq!(x.select1(x.count1()), None);
+        }
+    }
+
+    quickcheck! {
+        // quickcheck generates small numbers, scales them to cover full width words.
+        fn pdep_select1_eq_broadword_select1(x: u64, y: u64) -> bool {
+            let x = x.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ y;
+            (0..x.count1()).all(|c| {
+                let want = broadword_select1(x, c);
+                #[cfg(target_arch = "x86_64")]
+                {
+                    if is_x86_feature_detected!("bmi1") && is_x86_feature_detected!("bmi2") {
+                        return unsafe { pdep_select1(x, c) } == want;
+                    }
+                }
+                Some(want) == naive(x as u128, c)
+            })
+        }
+    }
+
+    #[test]
+    fn select1_u128() {
+        let mut rng = thread_rng();
+        let words = words(&mut rng);
+        for (&hi, &lo) in words.iter().zip(words.iter().rev()) {
+            let x = ((hi as u128) << 64) | lo as u128;
+            for c in 0..x.count1() {
+                assert_eq!(x.select1(c), naive(x, c));
+            }
+            assert_eq!(x.select1(x.count1()), None);
+        }
+    }
+}
@@ -195,6 +195,13 @@ pub trait FixedBits: Clone + Bits + BitsMut {
 
     /// Returns an empty instance.
     fn none() -> Self;
+
+    /// Counts `1` in `slice`, words override this to use SIMD kernels.
+    #[doc(hidden)]
+    #[inline]
+    fn count1_slice(slice: &[Self]) -> usize {
+        slice.iter().fold(0, |acc, block| acc + block.count1())
+    }
 }
 
 /// The mutable bit sequence.
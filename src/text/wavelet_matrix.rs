@@ -6,17 +6,24 @@ use std::{
 };
 
 use crate::{
-    bits::{self, to_exclusive, Words},
+    bits::{self, to_exclusive, Hybrid, Words},
     num::Word,
     ops::*,
     BitArray, BitMap,
 };
 
+mod mapped;
+mod persist;
+mod report;
 mod search;
+mod sums;
 mod trace;
 
 pub use {
+    mapped::{Lookup, Mapped, MappedSearch},
+    report::{ByPosition, Intersect, Positions, Report},
     search::{Max, Min, Search, Top},
+    sums::Sums,
     trace::Counts,
 };
 
@@ -28,7 +35,7 @@ pub struct WaveletMatrix<T, B> {
     // length of the original sequence
     size: usize,
 
-    // `tips` and `fids` have same size, T::DEPTH
+    // `tips` and `fids` have same size, the depth of levels that is `T::DEPTH` unless mapped
 
     // fully indexable dictionaries.
     fids: Vec<B>,
@@ -41,73 +48,152 @@ pub struct WaveletMatrix<T, B> {
     _sym: PhantomData<T>,
 }
 
-impl<'a, T, B> From<&'a mut [T]> for WaveletMatrix<T, BitArray<B>>
+/// A bit sequence that can be a level of `WaveletMatrix`.
+///
+/// A level is built in a mutable buffer of `size` bits, then frozen into `Self`.
+pub trait Level: Bits + Sized {
+    #[doc(hidden)]
+    type Buf: BitsMut;
+    #[doc(hidden)]
+    fn buf(size: usize) -> Self::Buf;
+    #[doc(hidden)]
+    fn freeze(buf: Self::Buf) -> Self;
+}
+
+impl<B: FixedBits> Level for BitArray<B>
 where
-    T: Code,
-    B: FixedBits,
     BitArray<B>: From<Vec<B>>,
 {
-    /// Builds WaveletMatrix and returns the sorted symbols.
-    /// Sorting is performed bit by bit so that symbols are sorted lexicographically.
-    fn from(bin0: &'a mut [T]) -> Self {
-        let size = bin0.len();
-        let _sym = PhantomData;
+    type Buf = Vec<B>;
+    fn buf(size: usize) -> Self::Buf {
+        vec![B::none(); bits::blocks_by(size, B::SIZE)]
+    }
+    fn freeze(buf: Self::Buf) -> Self {
+        BitArray::from(buf)
+    }
+}
 
-        let mut bin1 = bin0.to_vec();
-        let mut tips = vec![0; T::DEPTH];
-        let mut fids = Vec::with_capacity(T::DEPTH);
+impl<W: Word> Level for Hybrid<W> {
+    type Buf = Vec<W>;
+    fn buf(size: usize) -> Self::Buf {
+        vec![W::NONE; bits::blocks_by(size, W::BITS)]
+    }
+    fn freeze(buf: Self::Buf) -> Self {
+        Hybrid::from(buf)
+    }
+}
 
-        // for depth in 0..T::DEPTH {
-        for (depth, tip) in tips.iter_mut().enumerate().take(T::DEPTH) {
-            let mut node = vec![B::none(); bits::blocks_by(size, B::SIZE)];
+impl<B: Words> Level for BitMap<B> {
+    type Buf = BitMap<B>;
+    fn buf(size: usize) -> Self::Buf {
+        BitMap::none(size)
+    }
+    fn freeze(buf: Self::Buf) -> Self {
+        buf
+    }
+}
 
-            let mut l = 0; // the number of 0 in fids[depth]
-            let mut r = 0; // the number of 1 in fids[depth]
-            for i in 0..size {
-                if bin0[i].bit(T::DEPTH - depth - 1) {
-                    node.put1(i);
-                    bin1[r] = bin0[i];
-                    r += 1;
-                } else {
-                    bin0[l] = bin0[i];
-                    l += 1;
-                }
-            }
+impl<'a, T: Code, B: Level> From<&'a mut [T]> for WaveletMatrix<T, B> {
+    /// Builds WaveletMatrix and returns the sorted symbols, see `from_mut_slice`.
+    fn from(bin0: &'a mut [T]) -> Self {
+        WaveletMatrix::from_mut_slice(bin0)
+    }
+}
 
-            *tip = l;
-            // tips[depth] = l;
-            fids.push(BitArray::from(node));
-            debug_assert_eq!(l + r, size);
-            bin0[l..].copy_from_slice(&bin1[..r]);
-        }
+impl<'a, T: Code, B: Level> From<&'a [T]> for WaveletMatrix<T, B> {
+    fn from(slice: &'a [T]) -> Self {
+        WaveletMatrix::from_slice(slice)
+    }
+}
 
-        WaveletMatrix {
-            size,
-            tips,
-            fids,
-            _sym,
-        }
+impl<T: Code, B: Level> std::iter::FromIterator<T> for WaveletMatrix<T, B> {
+    /// Collects symbols into a scratch buffer, and builds WaveletMatrix from it.
+    fn from_iter<I: IntoIterator<Item = T>>(iterable: I) -> Self {
+        let mut bin0 = iterable.into_iter().collect::<Vec<T>>();
+        WaveletMatrix::from_mut_slice(&mut bin0)
     }
 }
 
-impl<'a, T: Code, B: Words> From<&'a mut [T]> for WaveletMatrix<T, BitMap<B>> {
-    /// Builds WaveletMatrix and returns the sorted symbols.
-    /// Sorting is performed bit by bit so that symbols are sorted lexicographically.
-    fn from(bin0: &mut [T]) -> Self {
+impl<T: Code, B: Level> WaveletMatrix<T, B> {
+    /// Builds WaveletMatrix without modifying `slice`.
+    ///
+    /// Building uses two scratch buffers of `slice.len()` symbols,
+    /// and a level buffer of `slice.len()` bits at a time.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    /// assert_eq!(wm.get(0), Some(5));
+    /// assert_eq!(vec[0], 5);
+    ///
+    /// let wm = vec.iter().cloned().collect::<WaveletMatrix<u8, BitArray<u64>>>();
+    /// assert_eq!(wm.get(11), Some(0));
+    /// ```
+    pub fn from_slice(slice: &[T]) -> Self {
+        WaveletMatrix::from_mut_slice(&mut slice.to_vec())
+    }
+
+    /// Builds WaveletMatrix from an iterator that knows its exact length.
+    ///
+    /// Unlike `collect`, scratch buffers are allocated once with the exact length,
+    /// so that building uses two buffers of `len` symbols and a level buffer of `len` bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator yields a different number of symbols than it reported.
+    ///
+    /// ```
+    /// use compacts::{bits::Hybrid, WaveletMatrix};
+    /// let wm = WaveletMatrix::<u16, Hybrid<u64>>::from_exact_iter((0..1000).map(|x| x % 7));
+    /// assert_eq!(wm.get(999), Some(999 % 7));
+    /// ```
+    pub fn from_exact_iter<I>(iterable: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = iterable.into_iter();
+        let len = iter.len();
+        let mut bin0 = Vec::with_capacity(len);
+        bin0.extend(iter);
+        assert_eq!(bin0.len(), len, "ExactSizeIterator reported a wrong length");
+        WaveletMatrix::from_mut_slice(&mut bin0)
+    }
+
+    /// Builds WaveletMatrix in place, and leaves `bin0` sorted.
+    ///
+    /// Symbols are partitioned stably bit by bit from the most significant bit,
+    /// so that `bin0` ends up sorted by bit-reversed symbols. Building uses a scratch buffer
+    /// of `bin0.len()` symbols, and a level buffer of `bin0.len()` bits at a time.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let mut vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_mut_slice(&mut vec);
+    /// assert_eq!(wm.get(0), Some(5));
+    /// assert!(vec.windows(2).all(|w| w[0].reverse_bits() <= w[1].reverse_bits()));
+    /// ```
+    pub fn from_mut_slice(bin0: &mut [T]) -> Self {
+        WaveletMatrix::build(bin0, T::DEPTH)
+    }
+
+    // Builds `levels` levels from the `levels` lower bits of symbols.
+    fn build(bin0: &mut [T], levels: usize) -> Self {
         let size = bin0.len();
+        let _sym = PhantomData;
 
         let mut bin1 = bin0.to_vec();
-        let mut tips = vec![0; T::DEPTH];
-        let mut fids = Vec::with_capacity(T::DEPTH);
+        let mut tips = vec![0; levels];
+        let mut fids = Vec::with_capacity(levels);
 
-        // for depth in 0..T::DEPTH {
-        for (depth, tip) in tips.iter_mut().enumerate().take(T::DEPTH) {
-            let mut node = BitMap::none(size);
+        for (depth, tip) in tips.iter_mut().enumerate() {
+            let mut node = B::buf(size);
 
             let mut l = 0; // the number of 0 in fids[depth]
             let mut r = 0; // the number of 1 in fids[depth]
             for i in 0..size {
-                if bin0[i].bit(T::DEPTH - depth - 1) {
+                if bin0[i].bit(levels - depth - 1) {
                     node.put1(i);
                     bin1[r] = bin0[i];
                     r += 1;
@@ -119,22 +205,27 @@ impl<'a, T: Code, B: Words> From<&'a mut [T]> for WaveletMatrix<T, BitMap<B>> {
 
             *tip = l;
             // tips[depth] = l;
-
-            fids.push(node);
+            fids.push(B::freeze(node));
             debug_assert_eq!(l + r, size);
             bin0[l..].copy_from_slice(&bin1[..r]);
         }
 
         WaveletMatrix {
-            _sym: PhantomData,
             size,
             tips,
             fids,
+            _sym,
         }
     }
 }
 
 impl<T, B> WaveletMatrix<T, B> {
+    /// Returns the number of levels.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.fids.len()
+    }
+
     pub fn view<R: RangeBounds<usize>>(&self, range: R) -> View<'_, Self> {
         View {
             idx: to_exclusive(&range, self.size),
@@ -154,8 +245,7 @@ impl<T: Word, B: Bits> WaveletMatrix<T, B> {
     /// ```
     /// use compacts::{BitArray, WaveletMatrix};
     /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
-    /// let mut cloned = vec.clone();
-    /// let wav = WaveletMatrix::<u8, BitArray<u64>>::from(cloned.as_mut_slice());
+    /// let wav = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
     ///
     /// for (i, &v) in vec.iter().enumerate() {
     ///     assert_eq!(wav.view(..).get(i), Some(v));
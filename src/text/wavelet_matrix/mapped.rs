@@ -0,0 +1,285 @@
+use std::ops::RangeBounds;
+
+use crate::ops::*;
+
+use super::{Counts, Level, Max, Min, Search, Top, View, WaveletMatrix};
+
+/// `Mapped` is a `WaveletMatrix` over a sparse or large domain of values.
+///
+/// Values are mapped to dense ranks by a sorted dictionary of distinct values,
+/// and `ceil(log2 σ)` levels are built over ranks, where `σ` is the number of distinct values.
+/// Queries translate values to ranks, and ranks back to values.
+///
+/// ```
+/// use compacts::{BitArray, text::wavelet_matrix::Mapped};
+/// let vec = vec![500u64, 40, 500, 500, 2, 1 << 40, 500, 6, 1 << 40, 3, 500, 0];
+/// let wm = Mapped::<u64, BitArray<u64>>::from_slice(&vec);
+/// assert_eq!(wm.values(), &[0, 2, 3, 6, 40, 500, 1 << 40]);
+/// assert_eq!(wm.depth(), 3);
+///
+/// assert_eq!(wm.get(5), Some(1 << 40));
+/// assert_eq!(wm.count(&500), 5);
+/// assert_eq!(wm.count(&501), 0);
+/// assert_eq!(wm.select(&500, 2), Some(3));
+///
+/// // [ 500, 40, 500, [ 500, 2, 1 << 40, 500, 6, 1 << 40, 3, 500 ], 0 ]
+/// let view = wm.view(3..11);
+/// assert_eq!(view.quantile(0), Some((0, 2)));
+/// assert_eq!(view.counts(&100).lt, 3);
+/// assert_eq!(view.counts(&100).gt, 5);
+/// assert_eq!(view.search(3, 1000).min().collect::<Vec<_>>(), vec![(1, 3), (1, 6), (3, 500)]);
+/// assert_eq!(view.top().next(), Some((3, 500)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapped<T, B> {
+    dict: Vec<T>, // sorted distinct values
+    ranks: WaveletMatrix<usize, B>,
+}
+
+/// `MappedSearch` is a `Search` over values of `Mapped`.
+#[derive(Debug, Clone)]
+pub struct MappedSearch<'a, T, B: Bits> {
+    dict: &'a [T],
+    search: Search<'a, WaveletMatrix<usize, B>>,
+}
+
+/// Translates `(count, rank)` of the inner iterator into `(count, value)`.
+#[derive(Debug, Clone)]
+pub struct Lookup<'a, T, I> {
+    dict: &'a [T],
+    iter: I,
+}
+
+impl<'a, T: Ord + Clone, B: Level> From<&'a [T]> for Mapped<T, B> {
+    fn from(slice: &'a [T]) -> Self {
+        Mapped::from_slice(slice)
+    }
+}
+
+impl<T: Ord + Clone, B: Level> std::iter::FromIterator<T> for Mapped<T, B> {
+    fn from_iter<I: IntoIterator<Item = T>>(iterable: I) -> Self {
+        let vec = iterable.into_iter().collect::<Vec<T>>();
+        Mapped::from_slice(&vec)
+    }
+}
+
+impl<T: Ord + Clone, B: Level> Mapped<T, B> {
+    /// Builds the dictionary of `slice`, and a `WaveletMatrix` over ranks in the dictionary.
+    pub fn from_slice(slice: &[T]) -> Self {
+        let mut dict = slice.to_vec();
+        dict.sort();
+        dict.dedup();
+
+        let mut ranks = slice
+            .iter()
+            .map(|val| dict.binary_search(val).expect("dict has all values"))
+            .collect::<Vec<usize>>();
+
+        // builds at least one level, so that a trace always has a leaf
+        let mut levels = 1;
+        while (1 << levels) < dict.len() {
+            levels += 1;
+        }
+        let ranks = WaveletMatrix::build(&mut ranks, levels);
+        Mapped { dict, ranks }
+    }
+}
+
+impl<T, B> Mapped<T, B> {
+    /// Returns the sorted distinct values.
+    #[inline]
+    pub fn values(&self) -> &[T] {
+        &self.dict
+    }
+
+    /// Returns the number of levels, `ceil(log2 σ)`.
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.ranks.depth()
+    }
+
+    /// Returns the number of elements in the sequence.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.ranks.size
+    }
+
+    pub fn view<R: RangeBounds<usize>>(&self, range: R) -> View<'_, Self> {
+        View {
+            idx: self.ranks.view(range).idx,
+            seq: self,
+        }
+    }
+}
+
+impl<T: Ord + Clone, B: Bits> Mapped<T, B> {
+    #[inline]
+    pub fn get(&self, i: usize) -> Option<T> {
+        self.view(..).get(i)
+    }
+
+    #[inline]
+    pub fn quantile(&self, k: usize) -> Option<(usize, T)> {
+        self.view(..).quantile(k)
+    }
+
+    /// Counts the occurrences of `val`.
+    #[inline]
+    pub fn count(&self, val: &T) -> usize {
+        self.view(..).count(val)
+    }
+
+    /// Counts the occurrences of `val` in `range`.
+    #[inline]
+    pub fn rank<R: RangeBounds<usize>>(&self, val: &T, range: R) -> usize {
+        self.view(range).count(val)
+    }
+
+    /// Returns the position of the `n`-th `val`.
+    #[inline]
+    pub fn select(&self, val: &T, n: usize) -> Option<usize> {
+        self.view(..).select(val, n)
+    }
+
+    #[inline]
+    pub fn search<Min, Max>(&self, min: Min, max: Max) -> MappedSearch<'_, T, B>
+    where
+        Min: Into<Option<T>>,
+        Max: Into<Option<T>>,
+    {
+        self.view(..).search(min, max)
+    }
+}
+
+impl<'a, T: Ord + Clone, B: Bits> View<'a, Mapped<T, B>> {
+    #[inline]
+    fn ranks(&self) -> View<'a, WaveletMatrix<usize, B>> {
+        View {
+            idx: self.idx,
+            seq: &self.seq.ranks,
+        }
+    }
+
+    // The rank of `val`, or the number of values less than `val`.
+    #[inline]
+    fn rank_of(&self, val: &T) -> Result<usize, usize> {
+        self.seq.dict.binary_search(val)
+    }
+
+    #[inline]
+    pub fn get(&self, i: usize) -> Option<T> {
+        let rank = self.ranks().get(i)?;
+        Some(self.seq.dict[rank].clone())
+    }
+
+    #[inline]
+    pub fn quantile(&self, k: usize) -> Option<(usize, T)> {
+        let (n, rank) = self.ranks().quantile(k)?;
+        Some((n, self.seq.dict[rank].clone()))
+    }
+
+    /// Counts the occurrences of `val` in this view.
+    pub fn count(&self, val: &T) -> usize {
+        self.rank_of(val)
+            .map_or(0, |rank| self.ranks().count(&rank))
+    }
+
+    /// Counts the occurrences of `val` in this view, and values less than or greater than `val`.
+    pub fn counts(&self, val: &T) -> Counts {
+        match self.rank_of(val) {
+            Ok(rank) => self.ranks().counts(&rank),
+            Err(rank) => {
+                let len = self.idx.map_or(0, |(i, j)| j - i);
+                let lt = if rank < self.seq.dict.len() {
+                    self.ranks().counts(&rank).lt
+                } else {
+                    len // `val` is greater than all values
+                };
+                Counts {
+                    lt,
+                    eq: 0,
+                    gt: len - lt,
+                }
+            }
+        }
+    }
+
+    /// Returns the position of the `n`-th `val` in this view.
+    pub fn select(&self, val: &T, n: usize) -> Option<usize> {
+        let rank = self.rank_of(val).ok()?;
+        self.ranks().select(&rank, n)
+    }
+
+    /// Builds a search such that return values satisfy `min <= value < max`.
+    pub fn search<Min, Max>(&self, min: Min, max: Max) -> MappedSearch<'a, T, B>
+    where
+        Min: Into<Option<T>>,
+        Max: Into<Option<T>>,
+    {
+        let lower = |val: T| self.rank_of(&val).unwrap_or_else(|rank| rank);
+        let min = min.into().map(lower);
+        let max = max.into().map(lower);
+
+        let mut ranks = self.ranks();
+        if max == Some(0) {
+            ranks.idx = None; // no values are less than `max`
+        }
+        MappedSearch {
+            dict: &self.seq.dict,
+            search: ranks.search(min, max),
+        }
+    }
+
+    #[inline]
+    pub fn top(&self) -> Lookup<'a, T, Top<'a, WaveletMatrix<usize, B>>> {
+        self.search(None, None).top()
+    }
+
+    #[inline]
+    pub fn min(&self) -> Lookup<'a, T, Min<'a, WaveletMatrix<usize, B>>> {
+        self.search(None, None).min()
+    }
+
+    #[inline]
+    pub fn max(&self) -> Lookup<'a, T, Max<'a, WaveletMatrix<usize, B>>> {
+        self.search(None, None).max()
+    }
+}
+
+impl<'a, T, B: Bits> MappedSearch<'a, T, B> {
+    /// Enumerates value that satisfy `min <= value < max` in ascending order.
+    pub fn min(self) -> Lookup<'a, T, Min<'a, WaveletMatrix<usize, B>>> {
+        Lookup {
+            dict: self.dict,
+            iter: self.search.min(),
+        }
+    }
+
+    /// Enumerates value that satisfy `min <= value < max` in descending order.
+    pub fn max(self) -> Lookup<'a, T, Max<'a, WaveletMatrix<usize, B>>> {
+        Lookup {
+            dict: self.dict,
+            iter: self.search.max(),
+        }
+    }
+
+    /// Enumerates value that satisfy `min <= value < max` from most frequent one.
+    pub fn top(self) -> Lookup<'a, T, Top<'a, WaveletMatrix<usize, B>>> {
+        Lookup {
+            dict: self.dict,
+            iter: self.search.top(),
+        }
+    }
+}
+
+impl<'a, T: Clone, I> Iterator for Lookup<'a, T, I>
+where
+    I: Iterator<Item = (usize, usize)>,
+{
+    type Item = (usize, T);
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (n, rank) = self.iter.next()?;
+        Some((n, self.dict[rank].clone()))
+    }
+}
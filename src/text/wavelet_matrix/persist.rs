@@ -0,0 +1,132 @@
+//! Binary format of `WaveletMatrix`.
+//!
+//! Every integer is little endian, see `bits::persist` for sequences.
+//!
+//! | field   | encoding                                        |
+//! |---------|-------------------------------------------------|
+//! | magic   | `b"WMTX"`                                       |
+//! | version | `u32`, currently `1`                            |
+//! | depth   | `u64`, `T::DEPTH` of the symbol type            |
+//! | tag     | length-prefixed UTF-8 of `Persist::tag` of `B`  |
+//! | size    | `u64`, the length of the original sequence      |
+//! | tips    | `depth` times `u64`                             |
+//! | fids    | `depth` levels, each written by `B::write_to`   |
+
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+};
+
+use super::WaveletMatrix;
+use crate::{
+    bits::persist::{self, Persist},
+    ops::*,
+};
+
+const MAGIC: &[u8; 4] = b"WMTX";
+const VERSION: u32 = 1;
+
+impl<T: Code, B: Bits + Persist> WaveletMatrix<T, B> {
+    /// Writes `self` to `w`, levels are written with their samples.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix, bits::Hybrid};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    ///
+    /// let mut buf = Vec::new();
+    /// wm.write_to(&mut buf).unwrap();
+    /// let read = WaveletMatrix::<u8, BitArray<u64>>::read_from(&mut buf.as_slice()).unwrap();
+    /// assert_eq!(wm, read);
+    ///
+    /// // the code width and the level type must match.
+    /// assert!(WaveletMatrix::<u16, BitArray<u64>>::read_from(&mut buf.as_slice()).is_err());
+    /// assert!(WaveletMatrix::<u8, BitArray<u32>>::read_from(&mut buf.as_slice()).is_err());
+    /// assert!(WaveletMatrix::<u8, Hybrid<u64>>::read_from(&mut buf.as_slice()).is_err());
+    /// ```
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        persist::write_usize(w, self.depth())?;
+        persist::write_bytes(w, B::tag().as_bytes())?;
+        persist::write_usize(w, self.size)?;
+        for &tip in &self.tips {
+            persist::write_usize(w, tip)?;
+        }
+        for fid in &self.fids {
+            fid.write_to(w)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a `WaveletMatrix` that `write_to` wrote, without rebuilding levels.
+    ///
+    /// Returns an error of `InvalidData` if the header doesn't match `T` and `B`,
+    /// or a tip doesn't match the number of 0s in its level.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&[5, 4, 5, 5, 2, 1]);
+    /// let mut buf = Vec::new();
+    /// wm.write_to(&mut buf).unwrap();
+    ///
+    /// // the first tip, that follows magic, version, depth, tag and size.
+    /// let at = 4 + 4 + 8 + (8 + "BitArray<u64>".len()) + 8;
+    /// buf[at] -= 1;
+    /// assert!(WaveletMatrix::<u8, BitArray<u64>>::read_from(&mut buf.as_slice()).is_err());
+    /// ```
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(persist::invalid("not a WaveletMatrix"));
+        }
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != VERSION {
+            return Err(persist::invalid(format!("unknown version {}", version)));
+        }
+
+        let depth = persist::read_usize(r)?;
+        if depth != T::DEPTH {
+            let msg = format!("depth mismatch: expected {}, found {}", T::DEPTH, depth);
+            return Err(persist::invalid(msg));
+        }
+        let tag = persist::read_bytes(r)?;
+        if tag != B::tag().as_bytes() {
+            let tag = String::from_utf8_lossy(&tag);
+            let msg = format!("level mismatch: expected {}, found {}", B::tag(), tag);
+            return Err(persist::invalid(msg));
+        }
+
+        let size = persist::read_usize(r)?;
+        let mut tips = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            let tip = persist::read_usize(r)?;
+            if tip > size {
+                return Err(persist::invalid("tip out of bounds"));
+            }
+            tips.push(tip);
+        }
+        let mut fids = Vec::with_capacity(depth);
+        for &tip in &tips {
+            let fid = B::read_from(r)?;
+            if fid.size() < size {
+                return Err(persist::invalid("level is shorter than the sequence"));
+            }
+            // a tip is the number of 0s in its level, `split` and `get` rely on it.
+            if fid.rank0(..size) != tip {
+                return Err(persist::invalid("tip doesn't match its level"));
+            }
+            fids.push(fid);
+        }
+
+        Ok(WaveletMatrix {
+            size,
+            fids,
+            tips,
+            _sym: PhantomData,
+        })
+    }
+}
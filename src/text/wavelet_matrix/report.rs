@@ -0,0 +1,461 @@
+use std::{
+    cmp::{self, Reverse},
+    collections::BinaryHeap,
+    iter::FusedIterator,
+    ops::{Bound, Range, RangeBounds},
+};
+
+use crate::{num::Word, ops::*};
+
+use super::{View, WaveletMatrix};
+
+/// Enumerates `(position, value)` pairs in a view whose value is in a range,
+/// ordered by value, and by position for the same value.
+#[derive(Debug, Clone)]
+pub struct Report<'a, T, B> {
+    seq: &'a WaveletMatrix<T, B>,
+    offset: usize,
+    range: Option<(T, T)>, // inclusive, None if empty
+    desc: bool,
+    stack: Vec<(usize, (usize, usize), T)>, // (depth, index, value) of nodes to visit
+    leaf: Option<Positions<'a, T, B>>,
+}
+
+/// Enumerates positions of a value in a view, see `View::positions`.
+#[derive(Debug, Clone)]
+pub struct Positions<'a, T, B> {
+    seq: &'a WaveletMatrix<T, B>,
+    offset: usize,
+    val: T,
+    leaf: Range<usize>, // elements of the leaf that are not located yet
+    chunk: usize,       // the number of elements to locate at once
+    front: std::vec::IntoIter<usize>, // located elements taken from the front of `leaf`
+    back: std::vec::IntoIter<usize>, // located elements taken from the back of `leaf`
+}
+
+/// Enumerates values that occur in both of two views, see `View::intersect`.
+#[derive(Debug, Clone)]
+pub struct Intersect<'a, T, B> {
+    seq: &'a WaveletMatrix<T, B>,
+    stack: Vec<Pair<T>>,
+}
+
+// (depth, index in the first view, index in the second view, value) of a pair of nodes.
+type Pair<T> = (usize, (usize, usize), (usize, usize), T);
+
+// (position of the first element, depth, index, value) of a node.
+type Cursor<T> = (usize, usize, (usize, usize), T);
+
+/// Enumerates `(position, value)` pairs in a view whose value is in a range, ordered by position.
+#[derive(Debug, Clone)]
+pub struct ByPosition<'a, T, B> {
+    seq: &'a WaveletMatrix<T, B>,
+    offset: usize,
+    range: Option<(T, T)>, // inclusive, None if empty
+    heap: BinaryHeap<Reverse<Cursor<T>>>,
+}
+
+impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
+    /// Returns an iterator over positions of `val` in this view, in ascending order.
+    ///
+    /// The leaf of `val` is traced only once, then positions are located lazily by chunks,
+    /// and each chunk walks back up once, selecting its elements in ascending order per level.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    ///
+    /// // [ 5, 4, 5, [ 5, 2, 1, 5, 6, 1, 3, 5 ], 0 ]
+    /// let view = wm.view(3..11);
+    /// assert_eq!(view.positions(&5).collect::<Vec<_>>(), vec![0, 3, 7]);
+    /// assert_eq!(view.positions(&5).rev().collect::<Vec<_>>(), vec![7, 3, 0]);
+    /// assert_eq!(view.positions(&5).len(), 3);
+    /// assert_eq!(view.positions(&4).next(), None);
+    /// ```
+    pub fn positions(&self, val: &T) -> Positions<'a, T, B> {
+        let leaf = self.leaf(val).map_or(0..0, |(i, j)| i..j);
+        Positions::new(self.seq, self.idx.map_or(0, |(i, _)| i), *val, leaf)
+    }
+}
+
+impl<'a, T: Word, B: Bits> View<'a, WaveletMatrix<T, B>> {
+    /// Reports positions in this view whose value is in `range`, in ascending order of values.
+    ///
+    /// Positions of a leaf are located lazily by chunks, see `positions`.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    ///
+    /// // [ 5, 4, 5, [ 5, 2, 1, 5, 6, 1, 3, 5 ], 0 ]
+    /// let view = wm.view(3..11);
+    /// let report = view.report(2..5).collect::<Vec<_>>();
+    /// assert_eq!(report, vec![(1, 2), (6, 3)]);
+    ///
+    /// // the 2 positions that have the largest values
+    /// let top = view.report_desc(..).take(2).collect::<Vec<_>>();
+    /// assert_eq!(top, vec![(4, 6), (0, 5)]);
+    ///
+    /// let report = view.report_by_position(..3).collect::<Vec<_>>();
+    /// assert_eq!(report, vec![(1, 2), (2, 1), (5, 1)]);
+    /// ```
+    pub fn report<R: RangeBounds<T>>(&self, range: R) -> Report<'a, T, B> {
+        self.reporter(range, false)
+    }
+
+    /// Reports positions in this view whose value is in `range`, in descending order of values.
+    pub fn report_desc<R: RangeBounds<T>>(&self, range: R) -> Report<'a, T, B> {
+        self.reporter(range, true)
+    }
+
+    /// Reports positions in this view whose value is in `range`, in ascending order of positions.
+    ///
+    /// Nodes are visited lazily in order of the position of their first element,
+    /// so a node is split only if it has the next position to report.
+    /// It takes `O(depth)` splits and locates for each position.
+    pub fn report_by_position<R: RangeBounds<T>>(&self, range: R) -> ByPosition<'a, T, B> {
+        let report = self.reporter(range, false);
+        let mut heap = BinaryHeap::new();
+        if let (Some((i, j)), Some(_)) = (self.idx, report.range) {
+            if i < j {
+                heap.push(Reverse((i, 0, (i, j), T::MIN)));
+            }
+        }
+        ByPosition {
+            seq: self.seq,
+            offset: report.offset,
+            range: report.range,
+            heap,
+        }
+    }
+
+    /// Returns values that occur in both `self` and `that`, in ascending order,
+    /// with the number of occurrences in each view as `(value, count_self, count_that)`.
+    ///
+    /// Both views are traced at the same time, and a node is pruned if it is empty in either view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if views are not of the same `WaveletMatrix`.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    ///
+    /// // [ [ 5, 4, 5, 5, 2, 1 ], 5, 6, 1, 3, 5, 0 ]
+    /// // [ 5, 4, 5, 5, 2, 1, [ 5, 6, 1, 3, 5, 0 ] ]
+    /// let common = wm.view(..6).intersect(&wm.view(6..)).collect::<Vec<_>>();
+    /// assert_eq!(common, vec![(1, 1, 1), (5, 3, 2)]);
+    /// ```
+    pub fn intersect(&self, that: &View<'a, WaveletMatrix<T, B>>) -> Intersect<'a, T, B> {
+        assert!(
+            std::ptr::eq(self.seq, that.seq),
+            "views of different sequences"
+        );
+        let mut stack = Vec::new();
+        if let (Some(lhs), Some(rhs)) = (self.idx, that.idx) {
+            stack.push((0, lhs, rhs, T::MIN));
+        }
+        Intersect {
+            seq: self.seq,
+            stack,
+        }
+    }
+
+    fn reporter<R: RangeBounds<T>>(&self, range: R, desc: bool) -> Report<'a, T, B> {
+        let lower = match range.start_bound() {
+            Bound::Included(&a) => Some(a),
+            Bound::Excluded(&a) if a < T::MAX => Some(a + T::_1),
+            Bound::Excluded(_) => None,
+            Bound::Unbounded => Some(T::MIN),
+        };
+        let upper = match range.end_bound() {
+            Bound::Included(&b) => Some(b),
+            Bound::Excluded(&b) if b > T::MIN => Some(b - T::_1),
+            Bound::Excluded(_) => None,
+            Bound::Unbounded => Some(T::MAX),
+        };
+        let range = match (lower, upper) {
+            (Some(a), Some(b)) if a <= b => Some((a, b)),
+            _ => None,
+        };
+
+        let mut stack = Vec::new();
+        if let (Some(idx), Some(_)) = (self.idx, range) {
+            stack.push((0, idx, T::MIN));
+        }
+        Report {
+            seq: self.seq,
+            offset: self.idx.map_or(0, |(i, _)| i),
+            range,
+            desc,
+            stack,
+            leaf: None,
+        }
+    }
+}
+
+// Returns true if a node at `depth` that has `value` may have values in `[lo, hi]`.
+fn overlaps<T: Word>(depth: usize, levels: usize, value: T, (lo, hi): (T, T)) -> bool {
+    // a node has values in `[value, value | free]`
+    value <= hi && lo <= value | free(depth, levels)
+}
+
+// Values that a node at `depth` of `levels` may have, the lower bits are free.
+fn free<T: Word>(depth: usize, levels: usize) -> T {
+    if depth >= levels {
+        T::MIN
+    } else {
+        T::MAX >> (T::BITS - (levels - depth))
+    }
+}
+
+impl<'a, T: Word, B: Bits> Report<'a, T, B> {
+    /// Visits nodes by depth first search, and returns the next leaf in `range`.
+    fn next_leaf(&mut self) -> Option<(T, Range<usize>)> {
+        let (lo, hi) = self.range?;
+        let desc = self.desc;
+        let levels = self.seq.depth();
+        while let Some((depth, (i, j), value)) = self.stack.pop() {
+            if depth == levels {
+                return Some((value, i..j));
+            }
+
+            let (lhs, rhs) = split(self.seq, depth, (i, j));
+            let mut one = value;
+            one.put1(levels - depth - 1);
+
+            let children = [(lhs, value), (rhs, one)];
+            let mut push = |&((i, j), value): &((usize, usize), T)| {
+                if i < j && overlaps(depth + 1, levels, value, (lo, hi)) {
+                    self.stack.push((depth + 1, (i, j), value));
+                }
+            };
+            // pushes the child to visit first at last
+            if desc {
+                children.iter().for_each(&mut push);
+            } else {
+                children.iter().rev().for_each(&mut push);
+            }
+        }
+        None
+    }
+}
+
+/// Splits the node at `depth` into its children, `lhs` for `0` and `rhs` for `1`.
+pub(super) fn split<T, B: Bits>(
+    seq: &WaveletMatrix<T, B>,
+    depth: usize,
+    (i, j): (usize, usize),
+) -> ((usize, usize), (usize, usize)) {
+    let fid = &seq.fids[depth];
+    let rank0_beg = fid.rank0(..i);
+    let rank0_end = fid.rank0(..j);
+    let tip = seq.tips[depth];
+    let lhs = (rank0_beg, rank0_end);
+    let rhs = (tip + i - rank0_beg, tip + j - rank0_end);
+    (lhs, rhs)
+}
+
+/// Locates the `k`-th element of the node of `value` at `depth` in the original sequence.
+fn locate<T: Code, B: Bits>(seq: &WaveletMatrix<T, B>, depth: usize, k: usize, value: T) -> usize {
+    let mut pos = k;
+    for depth in (0..depth).rev() {
+        let bit = value.bit(seq.depth() - depth - 1);
+        let tip = seq.tips[depth] * (bit as usize);
+        pos = seq.fids[depth]
+            .select(bit, pos - tip)
+            .expect("node has the element");
+    }
+    pos
+}
+
+// Elements at most this many `bit`s apart are found by reading words instead of `select`.
+const NEAR: usize = 64;
+
+/// Locates elements of the leaf of `value` in `ks`, walking back up once.
+///
+/// Elements of a leaf stay in ascending order at every level, so each element is searched
+/// from the previous one at the same level if they are near.
+fn locate_all<T: Code, B: Bits>(
+    seq: &WaveletMatrix<T, B>,
+    ks: Range<usize>,
+    value: T,
+) -> Vec<usize> {
+    let mut pos = ks.collect::<Vec<usize>>();
+    for depth in (0..seq.depth()).rev() {
+        let bit = value.bit(seq.depth() - depth - 1);
+        let tip = seq.tips[depth] * (bit as usize);
+        let fid = &seq.fids[depth];
+        let mut prev = None; // (rank, position) of the previous element
+        for p in pos.iter_mut() {
+            let rank = *p - tip;
+            *p = match prev {
+                Some((r, q)) if rank - r <= NEAR => scan(fid, bit, q + 1, rank - r - 1),
+                _ => fid.select(bit, rank).expect("leaf has the element"),
+            };
+            prev = Some((rank, *p));
+        }
+    }
+    pos
+}
+
+// Returns the position of the `n`-th `bit` from `i`, reading 64 bits at once.
+fn scan<B: Bits>(fid: &B, bit: bool, mut i: usize, mut n: usize) -> usize {
+    loop {
+        let len = cmp::min(64, fid.size() - i);
+        assert!(len > 0, "leaf has the element");
+        let mut word = fid.getn::<u64>(i, len);
+        if !bit {
+            word = !word;
+            if len < 64 {
+                word &= (1 << len) - 1;
+            }
+        }
+        match word.select1(n) {
+            Some(k) => return i + k,
+            None => {
+                n -= word.count1();
+                i += len;
+            }
+        }
+    }
+}
+
+impl<'a, T: Code, B: Bits> Positions<'a, T, B> {
+    fn new(seq: &'a WaveletMatrix<T, B>, offset: usize, val: T, leaf: Range<usize>) -> Self {
+        Positions {
+            seq,
+            offset,
+            val,
+            leaf,
+            chunk: 1,
+            front: Vec::new().into_iter(),
+            back: Vec::new().into_iter(),
+        }
+    }
+
+    // Doubles the chunk, so that taking a few positions does not locate the whole leaf.
+    fn next_chunk(&mut self) -> usize {
+        let chunk = self.chunk;
+        self.chunk = cmp::min(chunk * 2, 1024);
+        cmp::min(chunk, self.leaf.len())
+    }
+}
+
+impl<'a, T: Word, B: Bits> Iterator for Report<'a, T, B> {
+    type Item = (usize, T);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(leaf) = self.leaf.as_mut() {
+                if let Some(pos) = leaf.next() {
+                    return Some((pos, leaf.val));
+                }
+            }
+            let (value, leaf) = self.next_leaf()?;
+            self.leaf = Some(Positions::new(self.seq, self.offset, value, leaf));
+        }
+    }
+}
+
+impl<'a, T: Word, B: Bits> Iterator for ByPosition<'a, T, B> {
+    type Item = (usize, T);
+    fn next(&mut self) -> Option<Self::Item> {
+        let range = self.range?;
+        let levels = self.seq.depth();
+        while let Some(Reverse((pos, depth, (i, j), value))) = self.heap.pop() {
+            if depth == levels {
+                if i + 1 < j {
+                    let next = locate(self.seq, levels, i + 1, value);
+                    self.heap.push(Reverse((next, levels, (i + 1, j), value)));
+                }
+                return Some((pos - self.offset, value));
+            }
+
+            let (lhs, rhs) = split(self.seq, depth, (i, j));
+            let mut one = value;
+            one.put1(levels - depth - 1);
+            // the first element goes down to the child of its bit, and keeps its position.
+            let first = self.seq.fids[depth].bit(i);
+            for &(bit, (i, j), value) in &[(false, lhs, value), (true, rhs, one)] {
+                if i < j && overlaps(depth + 1, levels, value, range) {
+                    let pos = if bit == first {
+                        pos
+                    } else {
+                        locate(self.seq, depth + 1, i, value)
+                    };
+                    self.heap.push(Reverse((pos, depth + 1, (i, j), value)));
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<'a, T: Code, B: Bits> Iterator for Positions<'a, T, B> {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pos) = self.front.next() {
+                return Some(pos - self.offset);
+            }
+            if self.leaf.is_empty() {
+                return self.back.next().map(|pos| pos - self.offset);
+            }
+            let end = self.leaf.start + self.next_chunk();
+            self.front = locate_all(self.seq, self.leaf.start..end, self.val).into_iter();
+            self.leaf.start = end;
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.leaf.len() + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: Code, B: Bits> DoubleEndedIterator for Positions<'a, T, B> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pos) = self.back.next_back() {
+                return Some(pos - self.offset);
+            }
+            if self.leaf.is_empty() {
+                return self.front.next_back().map(|pos| pos - self.offset);
+            }
+            let start = self.leaf.end - self.next_chunk();
+            self.back = locate_all(self.seq, start..self.leaf.end, self.val).into_iter();
+            self.leaf.end = start;
+        }
+    }
+}
+
+impl<'a, T: Code, B: Bits> ExactSizeIterator for Positions<'a, T, B> {}
+
+impl<'a, T: Code, B: Bits> FusedIterator for Positions<'a, T, B> {}
+
+impl<'a, T: Word, B: Bits> Iterator for Intersect<'a, T, B> {
+    type Item = (T, usize, usize);
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((depth, (i1, j1), (i2, j2), value)) = self.stack.pop() {
+            if i1 == j1 || i2 == j2 {
+                continue;
+            }
+            if depth == self.seq.depth() {
+                return Some((value, j1 - i1, j2 - i2));
+            }
+
+            let (lhs1, rhs1) = split(self.seq, depth, (i1, j1));
+            let (lhs2, rhs2) = split(self.seq, depth, (i2, j2));
+            let mut one = value;
+            one.put1(self.seq.depth() - depth - 1);
+            // pushes `rhs` first, to visit smaller values first
+            self.stack.push((depth + 1, rhs1, rhs2, one));
+            self.stack.push((depth + 1, lhs1, lhs2, value));
+        }
+        None
+    }
+}
@@ -239,8 +239,8 @@ where
     T::Code: Word,
     Probe<T::Code, By>: Ord,
 {
-    fn push(&mut self, probe: Probe<T::Code, By>) {
-        if let Some(probe) = probe.guard(self.min, self.max) {
+    fn push(&mut self, probe: Probe<T::Code, By>, levels: usize) {
+        if let Some(probe) = probe.guard(self.min, self.max, levels) {
             self.bin.push(probe);
         }
     }
@@ -261,7 +261,8 @@ where
             _kind,
         }) = self.bin.pop()
         {
-            if depth == T::DEPTH {
+            let levels = self.seq.depth();
+            if depth == levels {
                 return Some((j - i, value));
             }
 
@@ -276,16 +277,19 @@ where
                 let index = (rank0_beg, rank0_end);
                 let depth = depth + 1;
 
-                self.push(Probe {
-                    index,
-                    depth,
-                    value,
-                    _kind: PhantomData,
-                });
+                self.push(
+                    Probe {
+                        index,
+                        depth,
+                        value,
+                        _kind: PhantomData,
+                    },
+                    levels,
+                );
             }
 
             if rank1_beg < rank1_end {
-                value.put1(T::DEPTH - depth - 1);
+                value.put1(levels - depth - 1);
 
                 let index = {
                     let tip = self.seq.tips[depth];
@@ -293,12 +297,15 @@ where
                 };
                 let depth = depth + 1;
 
-                self.push(Probe {
-                    index,
-                    depth,
-                    value,
-                    _kind: PhantomData,
-                });
+                self.push(
+                    Probe {
+                        index,
+                        depth,
+                        value,
+                        _kind: PhantomData,
+                    },
+                    levels,
+                );
             };
         }
         None
@@ -314,8 +321,13 @@ mod cmpby {
     use crate::ops::Code;
 
     impl<'a, By, T: Word> Probe<T, By> {
-        pub(super) fn guard(self, min: Option<T>, max: Option<T>) -> Option<Probe<T, By>> {
-            let prefix = |sym, d| sym >> (T::DEPTH - d);
+        pub(super) fn guard(
+            self,
+            min: Option<T>,
+            max: Option<T>,
+            levels: usize,
+        ) -> Option<Probe<T, By>> {
+            let prefix = |sym, d| sym >> (levels - d);
 
             let val = prefix(self.value, self.depth);
             let min = min.map(|min| prefix(min, self.depth));
@@ -0,0 +1,230 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::{
+    bits::to_exclusive,
+    num::{cast, Word},
+    ops::*,
+};
+
+use super::{report::split, WaveletMatrix};
+
+/// `Sums` has cumulative sums of values for each level of a `WaveletMatrix`.
+///
+/// Sums of a level are stored in `u64` if the sum of all values can't exceed `u64`,
+/// i.e. `T::BITS` plus the bit width of the length is at most 64, and in `u128` otherwise.
+/// So sums cost `8 * depth` or `16 * depth` bytes per value, that is more than the matrix itself.
+#[derive(Debug, Clone)]
+pub struct Sums<'a, T, B> {
+    seq: &'a WaveletMatrix<T, B>,
+    levels: Levels,
+}
+
+// `levels[d][p]` is the sum of the first `p` values, ordered as the level `d + 1`.
+#[derive(Debug, Clone)]
+enum Levels {
+    Narrow(Vec<Vec<u64>>),
+    Wide(Vec<Vec<u128>>),
+}
+
+impl<T: Word, B: Bits> WaveletMatrix<T, B> {
+    /// Builds cumulative sums of each level, that `range_sum` and `sum_smallest_k` use.
+    ///
+    /// Values are recovered level by level in the order that building partitioned them,
+    /// instead of calling `get` for each position.
+    /// Building uses a scratch buffer of `len` symbols and two buffers of `len` positions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sum of all values overflows `u128`.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    /// let sums = wm.sums();
+    ///
+    /// // [ 5, 4, 5, [ 5, 2, 1, 5, 6, 1, 3, 5 ], 0 ]
+    /// assert_eq!(sums.range_sum(3..11, ..), 28);
+    /// assert_eq!(sums.range_sum(3..11, ..5), 7);
+    /// assert_eq!(sums.range_sum(3..11, 2..=5), 20);
+    ///
+    /// // empty and reversed ranges have no values.
+    /// assert_eq!(sums.range_sum(3..11, 3..3), 0);
+    /// assert_eq!(sums.range_sum(3..11, 5..2), 0);
+    ///
+    /// // 1 + 1 + 2
+    /// assert_eq!(sums.sum_smallest_k(3..11, 3), 4);
+    /// assert_eq!(sums.sum_smallest_k(3..11, 100), 28);
+    ///
+    /// // sums are wider than values.
+    /// let wm = WaveletMatrix::<usize, BitArray<u64>>::from_slice(&[usize::MAX; 3]);
+    /// assert_eq!(wm.sums().range_sum(.., ..), 3 * usize::MAX as u128);
+    /// ```
+    pub fn sums(&self) -> Sums<'_, T, B> {
+        let mut idx = (0..self.size).collect::<Vec<usize>>();
+        let mut ones = Vec::with_capacity(self.size);
+
+        // sets the bit of each level to values, following the positions through levels.
+        let mut vals = vec![T::MIN; self.size];
+        for (depth, fid) in self.fids.iter().enumerate() {
+            let bit = self.depth() - depth - 1;
+            partition(&mut idx, &mut ones, self.tips[depth], |p, i| {
+                let one = fid.bit(p);
+                if one {
+                    vals[i].put1(bit);
+                }
+                one
+            });
+        }
+
+        let width = 64 - (self.size as u64).leading_zeros() as usize;
+        let levels = if T::BITS + width <= 64 {
+            Levels::Narrow(self.prefix_sums(&vals, &mut idx, &mut ones, cast))
+        } else {
+            Levels::Wide(self.prefix_sums(&vals, &mut idx, &mut ones, |sum| sum))
+        };
+        Sums { seq: self, levels }
+    }
+
+    // Sums `vals` cumulatively in the order of each level, and stores sums as `S`.
+    fn prefix_sums<S, F>(
+        &self,
+        vals: &[T],
+        idx: &mut Vec<usize>,
+        ones: &mut Vec<usize>,
+        f: F,
+    ) -> Vec<Vec<S>>
+    where
+        F: Fn(u128) -> S,
+    {
+        idx.clear();
+        idx.extend(0..self.size);
+        let mut levels = Vec::with_capacity(self.depth());
+        for depth in 0..self.depth() {
+            let bit = self.depth() - depth - 1;
+            partition(idx, ones, self.tips[depth], |_, i| vals[i].bit(bit));
+
+            let mut sum = 0u128;
+            let mut level = Vec::with_capacity(self.size + 1);
+            level.push(f(sum));
+            for &i in idx.iter() {
+                sum = sum.checked_add(value(vals[i])).expect("sum overflows u128");
+                level.push(f(sum));
+            }
+            levels.push(level);
+        }
+        levels
+    }
+}
+
+// Partitions positions stably as `build` does, `one(p, idx[p])` tells if `idx[p]` goes to 1s.
+fn partition<F>(idx: &mut Vec<usize>, ones: &mut Vec<usize>, zeros: usize, mut one: F)
+where
+    F: FnMut(usize, usize) -> bool,
+{
+    ones.clear();
+    let mut l = 0;
+    for p in 0..idx.len() {
+        let i = idx[p];
+        if one(p, i) {
+            ones.push(i);
+        } else {
+            idx[l] = i;
+            l += 1;
+        }
+    }
+    debug_assert_eq!(l, zeros);
+    idx.truncate(l);
+    idx.extend_from_slice(ones);
+}
+
+fn value<T: Word>(v: T) -> u128 {
+    v.getn(0, T::BITS)
+}
+
+impl<'a, T: Word, B: Bits> Sums<'a, T, B> {
+    /// Returns the sum of values in `index` whose value is in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn range_sum<I, R>(&self, index: I, range: R) -> u128
+    where
+        I: RangeBounds<usize>,
+        R: RangeBounds<T>,
+    {
+        let idx = to_exclusive(&index, self.seq.size).expect("out of bounds");
+        let upper = match range.end_bound() {
+            Bound::Included(&b) => self.sum_below(idx, b, true),
+            Bound::Excluded(&b) => self.sum_below(idx, b, false),
+            Bound::Unbounded => self.sum_below(idx, T::MAX, true),
+        };
+        let lower = match range.start_bound() {
+            Bound::Included(&a) => self.sum_below(idx, a, false),
+            Bound::Excluded(&a) => self.sum_below(idx, a, true),
+            Bound::Unbounded => 0,
+        };
+        upper.saturating_sub(lower)
+    }
+
+    /// Returns the sum of the `k` smallest values in `index`,
+    /// or the sum of all values if `index` has less than `k` values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn sum_smallest_k<I>(&self, index: I, k: usize) -> u128
+    where
+        I: RangeBounds<usize>,
+    {
+        let (mut i, mut j) = to_exclusive(&index, self.seq.size).expect("out of bounds");
+        let mut k = std::cmp::min(k, j - i);
+        let mut sum = 0u128;
+        let mut val = T::MIN;
+        let levels = self.seq.depth();
+        for depth in 0..levels {
+            let (lhs, rhs) = split(self.seq, depth, (i, j));
+            let zeros = lhs.1 - lhs.0;
+            if k > zeros {
+                sum += self.sum(depth, lhs);
+                k -= zeros;
+                val.put1(levels - depth - 1);
+                i = rhs.0;
+                j = rhs.1;
+            } else {
+                i = lhs.0;
+                j = lhs.1;
+            }
+        }
+        sum + k as u128 * value(val)
+    }
+
+    // Sums values in `idx` that are less than `x`, or equal to if `eq`.
+    fn sum_below(&self, (mut i, mut j): (usize, usize), x: T, eq: bool) -> u128 {
+        let mut sum = 0u128;
+        let levels = self.seq.depth();
+        for depth in 0..levels {
+            let (lhs, rhs) = split(self.seq, depth, (i, j));
+            if x.bit(levels - depth - 1) {
+                sum += self.sum(depth, lhs);
+                i = rhs.0;
+                j = rhs.1;
+            } else {
+                i = lhs.0;
+                j = lhs.1;
+            }
+        }
+        if eq {
+            sum += (j - i) as u128 * value(x);
+        }
+        sum
+    }
+
+    // Sums values of the node `(i, j)` in the level `depth + 1`.
+    fn sum(&self, depth: usize, (i, j): (usize, usize)) -> u128 {
+        match &self.levels {
+            Levels::Narrow(levels) => u128::from(levels[depth][j] - levels[depth][i]),
+            Levels::Wide(levels) => levels[depth][j] - levels[depth][i],
+        }
+    }
+}
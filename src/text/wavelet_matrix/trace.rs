@@ -1,6 +1,12 @@
-use std::iter::{Enumerate, FusedIterator};
+use std::{
+    iter::{Enumerate, FusedIterator},
+    ops::{Bound, RangeBounds},
+};
 
-use crate::{num::Word, ops::Bits, ops::Code};
+use crate::{
+    num::Word,
+    ops::{Bits, BitsMut, Code},
+};
 
 use super::{Row, Rows, View, WaveletMatrix};
 
@@ -21,6 +27,20 @@ fn trace_by<B, F>(index: (usize, usize), rows: Rows<'_, B>, router: F) -> Trace<
     }
 }
 
+// Starts tracing at `depth`, where `index` is the node at `depth`.
+fn trace_from<B, F>(
+    depth: usize,
+    index: (usize, usize),
+    rows: Rows<'_, B>,
+    router: F,
+) -> Trace<'_, B, F> {
+    let mut trace = trace_by(index, rows, router);
+    if depth > 0 {
+        trace.rows.nth(depth - 1);
+    }
+    trace
+}
+
 fn by_value<B, T>(
     index: (usize, usize),
     rows: Rows<'_, B>,
@@ -29,8 +49,9 @@ fn by_value<B, T>(
 where
     T: Code,
 {
+    let levels = rows.len();
     trace_by(index, rows, move |Data { depth, .. }| {
-        Route::from_bit(val.bit(T::DEPTH - depth - 1))
+        Route::from_bit(val.bit(levels - depth - 1))
     })
 }
 
@@ -48,6 +69,14 @@ impl<'a, T: Code, B> View<'a, WaveletMatrix<T, B>> {
             .as_ref()
             .map(|&idx| trace_by(idx, self.seq.rows(), router))
     }
+
+    // The index of the leaf of `val`, where its occurrences in this view are.
+    pub(super) fn leaf(&self, val: &T) -> Option<(usize, usize)>
+    where
+        B: Bits,
+    {
+        self.trace(*val).map(|trace| trace.last().unwrap().index)
+    }
 }
 
 impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
@@ -67,7 +96,7 @@ impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
             let mut sym = T::MIN;
             for (depth, fid) in seq.fids.iter().enumerate() {
                 if fid.bit(i) {
-                    sym.put1(T::DEPTH - depth - 1);
+                    sym.put1(seq.depth() - depth - 1);
                     i = fid.rank1(..i) + seq.tips[depth]; // add count0 if b is 1
                 } else {
                     i = fid.rank0(..i);
@@ -111,13 +140,14 @@ impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
         self.idx.as_ref().and_then(|&idx| {
             let seq = self.seq;
 
+            let levels = seq.depth();
             let mut sym = T::MIN;
 
             let rf = |data: Data| {
                 if k < data.rank0 {
                     Route::Lhs
                 } else {
-                    sym.put1(T::DEPTH - data.depth - 1);
+                    sym.put1(levels - data.depth - 1);
                     k -= data.rank0;
                     Route::Rhs
                 }
@@ -162,9 +192,10 @@ impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
     pub fn counts(&self, val: &T) -> Counts {
         let mut lt = 0;
         let mut gt = 0;
+        let levels = self.seq.depth();
         let eq = {
             let rf = |data: Data| {
-                if val.bit(T::DEPTH - data.depth - 1) {
+                if val.bit(levels - data.depth - 1) {
                     lt += data.rank0;
                     Route::Rhs
                 } else {
@@ -181,6 +212,166 @@ impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
         Counts { eq, lt, gt }
     }
 
+    /// Returns the smallest value that is greater than or equal to `x` in this view.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    ///
+    /// // [ 5, 4, 5, [ 5, 2, 1, 5, 6, 1, 3, 5 ], 0 ]
+    /// let view = wm.view(3..11);
+    /// assert_eq!(view.next_value(&0), Some(1));
+    /// assert_eq!(view.next_value(&3), Some(3));
+    /// assert_eq!(view.next_value(&4), Some(5));
+    /// assert_eq!(view.next_value(&7), None);
+    ///
+    /// assert_eq!(view.prev_value(&0), None);
+    /// assert_eq!(view.prev_value(&1), None);
+    /// assert_eq!(view.prev_value(&5), Some(3));
+    /// assert_eq!(view.prev_value(&255), Some(6));
+    /// ```
+    pub fn next_value(&self, x: &T) -> Option<T>
+    where
+        T: Word,
+    {
+        // where `x` goes to the left but the right has values
+        let (found, branch) =
+            self.branch(*x, |route, len, parent| route == Route::Lhs && len < parent);
+        if found {
+            return Some(*x);
+        }
+        let (depth, index) = branch?;
+
+        // turns to the right at `depth`, then goes to the leftmost leaf
+        let levels = self.seq.depth();
+        let mut sym = prefix(*x, depth, levels);
+        let rf = |data: Data| {
+            if data.depth == depth || data.rank0 == 0 {
+                sym.put1(levels - data.depth - 1);
+                Route::Rhs
+            } else {
+                Route::Lhs
+            }
+        };
+        trace_from(depth, index, self.seq.rows(), rf).for_each(drop);
+        Some(sym)
+    }
+
+    /// Returns the largest value that is less than `x` in this view.
+    pub fn prev_value(&self, x: &T) -> Option<T>
+    where
+        T: Word,
+    {
+        // where `x` goes to the right but the left has values
+        let (_, branch) = self.branch(*x, |route, len, parent| route == Route::Rhs && len < parent);
+        let (depth, index) = branch?;
+
+        // turns to the left at `depth`, then goes to the rightmost leaf
+        let levels = self.seq.depth();
+        let mut sym = prefix(*x, depth, levels);
+        let rf = |data: Data| {
+            if data.depth != depth && data.rank1 > 0 {
+                sym.put1(levels - data.depth - 1);
+                Route::Rhs
+            } else {
+                Route::Lhs
+            }
+        };
+        trace_from(depth, index, self.seq.rows(), rf).for_each(drop);
+        Some(sym)
+    }
+
+    /// Traces `x`, and returns whether this view has `x`,
+    /// and the deepest node `(depth, index)` where `at(route, len, parent_len)` holds.
+    fn branch<F>(&self, x: T, at: F) -> (bool, Option<At>)
+    where
+        F: Fn(Route, usize, usize) -> bool,
+    {
+        let mut deepest = None;
+        let mut parent = match self.idx {
+            Some(idx) => idx,
+            None => return (false, None),
+        };
+        for Node {
+            depth,
+            index,
+            route,
+        } in by_value(parent, self.seq.rows(), x)
+        {
+            if at(route, index.1 - index.0, parent.1 - parent.0) {
+                deepest = Some((depth - 1, parent));
+            }
+            if index.0 == index.1 {
+                return (false, deepest);
+            }
+            parent = index;
+        }
+        (true, deepest)
+    }
+
+    /// Counts values in `range` in this view, the 2D dominance count.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    ///
+    /// // [ 5, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0 ]
+    /// assert_eq!(wm.view(..).range_freq(2..5), 3);
+    /// assert_eq!(wm.view(..).range_freq(5..), 6);
+    /// assert_eq!(wm.view(..).range_freq(..=1), 3);
+    ///
+    /// // [ 5, 4, 5, [ 5, 2, 1, 5, 6, 1, 3, 5 ], 0 ]
+    /// assert_eq!(wm.view(3..11).range_freq(2..6), 5);
+    /// assert_eq!(wm.view(3..11).range_freq(..), 8);
+    /// ```
+    pub fn range_freq<R: RangeBounds<T>>(&self, range: R) -> usize {
+        let len = self.idx.map_or(0, |(i, j)| j - i);
+        // the number of values less than the bound
+        let lower = match range.start_bound() {
+            Bound::Included(a) => self.counts(a).lt,
+            Bound::Excluded(a) => {
+                let counts = self.counts(a);
+                counts.lt + counts.eq
+            }
+            Bound::Unbounded => 0,
+        };
+        let upper = match range.end_bound() {
+            Bound::Included(b) => {
+                let counts = self.counts(b);
+                counts.lt + counts.eq
+            }
+            Bound::Excluded(b) => self.counts(b).lt,
+            Bound::Unbounded => len,
+        };
+        upper.saturating_sub(lower)
+    }
+
+    /// Counts values for each bucket `[bounds[k], bounds[k+1])` in this view.
+    ///
+    /// `bounds` must be sorted, and the result has `bounds.len() - 1` counts.
+    /// Each bound is traced once, and shared by adjacent buckets.
+    ///
+    /// ```
+    /// use compacts::{BitArray, WaveletMatrix};
+    /// let vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
+    /// let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    /// assert_eq!(wm.view(..).range_freqs(&[0, 2, 5, 7]), vec![3, 3, 6]);
+    /// assert_eq!(wm.view(3..11).range_freqs(&[0, 4, 8]), vec![4, 4]);
+    /// ```
+    pub fn range_freqs(&self, bounds: &[T]) -> Vec<usize>
+    where
+        T: Word,
+    {
+        assert!(
+            bounds.windows(2).all(|w| w[0] <= w[1]),
+            "bounds must be sorted"
+        );
+        let lts = bounds.iter().map(|b| self.counts(b).lt).collect::<Vec<_>>();
+        lts.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
     /// ```
     /// use compacts::{BitArray, WaveletMatrix, ops::Select};
     /// let mut vec = vec![5u8, 4, 5, 5, 2, 1, 5, 6, 1, 3, 5, 0];
@@ -221,9 +412,9 @@ impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
                 None
             } else {
                 let mut pos = i + n;
-                for depth in (0..T::DEPTH).rev() {
+                for depth in (0..seq.depth()).rev() {
                     let fid = &seq.fids[depth];
-                    let bit = val.bit(T::DEPTH - depth - 1);
+                    let bit = val.bit(seq.depth() - depth - 1);
                     let tip = seq.tips[depth] * (bit as usize);
                     pos = fid.select(bit, pos - tip).unwrap();
                 }
@@ -285,6 +476,20 @@ impl<'a, T: Code, B: Bits> View<'a, WaveletMatrix<T, B>> {
 //     }
 // }
 
+// `(depth, index)` of a node.
+type At = (usize, (usize, usize));
+
+// The upper `depth` bits of `x` that has `levels` bits.
+fn prefix<T: Word>(x: T, depth: usize, levels: usize) -> T {
+    let mut sym = T::MIN;
+    for d in 0..depth {
+        if x.bit(levels - d - 1) {
+            sym.put1(levels - d - 1);
+        }
+    }
+    sym
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 struct Node {
     depth: usize,
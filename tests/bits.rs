@@ -1,9 +1,11 @@
 #[allow(unused_imports)]
 use {
     compacts::{
-        bits::{and, or, xor, Fold, Mask},
+        bits::{
+            and, and_not, or, xor, Difference, Fold, Intersection, Mask, SymmetricDifference, Union,
+        },
         ops::*,
-        BitArray, BitMap, WaveletMatrix,
+        BitArray, BitMap, Pop, WaveletMatrix,
     },
     lazy_static::lazy_static,
     rand::prelude::*,
@@ -133,6 +135,24 @@ mod mask {
         assert_eq!(vec1, vec2);
     }
 
+    #[test]
+    fn seek() {
+        let mut steps = M0.into_steps();
+        let mut naive = M0.into_steps();
+        for index in (0..BOUND / (1 << 15)).step_by(7) {
+            let found = <&BitMap<[u64; 512]> as Mask>::seek(&mut steps, index);
+            assert_eq!(found, naive.find(|&(i, _)| i >= index));
+        }
+
+        // a sparse input makes the others jump.
+        let mut sparse = BitMap::<[u64; 512]>::none(BOUND);
+        sparse.put1(BOUND / 2);
+        sparse.put1(BOUND - 1);
+        let vec1 = Fold::and(vec![&*M0, &sparse, &*M1]).collect::<Vec<_>>();
+        let vec2 = M0.and(&sparse).and(&*M1).collect::<Vec<_>>();
+        assert_eq!(vec1, vec2);
+    }
+
     #[test]
     fn fold_or() {
         let vec1 = Fold::or(vec![&*M0, &*M1, &*M2]).collect::<Vec<_>>();
@@ -140,6 +160,169 @@ mod mask {
         assert_eq!(vec1, vec2);
     }
 
+    #[test]
+    fn fold_and_not() {
+        let vec1 = Fold::and_not(vec![&*M0, &*M1, &*M2]).collect::<Vec<_>>();
+        let vec2 = M0.and_not(&*M1).and_not(&*M2).collect::<Vec<_>>();
+        assert_eq!(vec1, vec2);
+    }
+
+    #[test]
+    fn fold_many() {
+        let many = [&*M0, &*M1, &*M2].repeat(20);
+        let map = M0.or(&*M1).or(&*M2).materialize::<Vec<u64>>();
+        assert_eq!(Fold::or(many.clone()).materialize::<Vec<u64>>(), map);
+        let map = M0.and(&*M1).and(&*M2).materialize::<Vec<u64>>();
+        assert_eq!(Fold::and(many.clone()).materialize::<Vec<u64>>(), map);
+        assert_eq!(Fold::xor(many.clone()).count_ones(), 0);
+        assert_eq!(Fold::and_not(many).count_ones(), 0);
+    }
+
+    #[test]
+    fn threshold() {
+        let data = vec![&*M0, &*M1, &*M2];
+        let or = Fold::or(data.clone()).materialize::<Vec<u64>>();
+        let and = Fold::and(data.clone()).materialize::<Vec<u64>>();
+        let two = M0
+            .and(&*M1)
+            .or(M0.and(&*M2))
+            .or(M1.and(&*M2))
+            .materialize::<Vec<u64>>();
+        assert_eq!(
+            Fold::threshold(1, data.clone()).materialize::<Vec<u64>>(),
+            or
+        );
+        assert_eq!(
+            Fold::threshold(2, data.clone()).materialize::<Vec<u64>>(),
+            two
+        );
+        assert_eq!(
+            Fold::threshold(3, data.clone()).materialize::<Vec<u64>>(),
+            and
+        );
+        assert_eq!(Fold::threshold(4, data).count_ones(), 0);
+    }
+
+    #[test]
+    fn not_within() {
+        let not = M0.not::<[u64; 512]>(BOUND).materialize::<Vec<u64>>();
+        assert_eq!(not.count1(), BOUND - M0.count1());
+        assert_eq!(not.size(), BOUND + 512 * 64 - BOUND % (512 * 64));
+        assert_eq!(not.rank1(..BOUND), BOUND - M0.count1());
+        assert_eq!(M0.and(M0.not::<[u64; 512]>(BOUND)).count_ones(), 0);
+        assert_eq!(M0.not::<[u64; 512]>(BOUND).count_ones(), not.count1());
+        assert_eq!(M0.not::<[u64; 512]>(BOUND).first1(), not.select1(0));
+
+        let vec = M0.and_not(&*M1).materialize::<Vec<u64>>();
+        let mut rng = thread_rng();
+        for _ in 0..10 {
+            let i = rng.gen_range(0, BOUND);
+            let j = rng.gen_range(i, BOUND);
+            let count1 = M0.rank1(..j) - M0.rank1(..i);
+            assert_eq!(M0.within(i..j).count_ones(), count1);
+            assert_eq!(M0.within(i..j).is_empty(), count1 == 0);
+            let first1 = M0.select1(M0.rank1(..i)).filter(|&p| p < j);
+            assert_eq!(M0.within(i..j).first1(), first1);
+
+            let within = M0.and_not(&*M1).within(i..j);
+            let j = std::cmp::min(j, vec.size());
+            let i = std::cmp::min(i, j);
+            assert_eq!(within.count_ones(), vec.rank1(..j) - vec.rank1(..i));
+        }
+    }
+
+    #[test]
+    fn materialize() {
+        let map = M0.and(&*M1).materialize::<BitMap<[u64; 512]>>();
+        let vec = M0.and(&*M1).materialize::<Vec<u64>>();
+        let arr = M0.and(&*M1).materialize::<BitArray<u64>>();
+        let pop = M0.and(&*M1).materialize::<Pop<u64>>();
+
+        let mut cloned = M0.clone();
+        cloned.intersection(&*M1);
+        let count1 = cloned.count1();
+        assert_eq!(map.count1(), count1);
+        assert_eq!(vec.count1(), count1);
+        assert_eq!(arr.count1(), count1);
+        assert_eq!(pop.count1(), count1);
+
+        for n in (0..count1).step_by(97) {
+            let pos = cloned.select1(n);
+            assert_eq!(map.select1(n), pos);
+            assert_eq!(vec.select1(n), pos);
+            assert_eq!(arr.select1(n), pos);
+            assert_eq!(pop.select1(n), pos);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "block type")]
+    fn not_other_blocks() {
+        // `M0` has blocks of 512 words.
+        M0.not::<[u64; 1024]>(BOUND).count_ones();
+    }
+
+    #[test]
+    fn materialize_in() {
+        use compacts::bits::ewah::Ewah;
+        // not a multiple of blocks, steps past `len` are clipped.
+        let len = BOUND - 1000;
+        let map = M0.or(&*M1).materialize_in::<BitMap<[u64; 512]>>(len);
+        let vec = M0.or(&*M1).materialize_in::<Vec<u64>>(len);
+        let arr = M0.or(&*M1).materialize_in::<BitArray<u64>>(len);
+        let pop = M0.or(&*M1).materialize_in::<Pop<u64>>(len);
+        let ewah = M0.or(&*M1).materialize_in::<Ewah<u64>>(len);
+
+        let count1 = M0.or(&*M1).within(..len).count_ones();
+        assert_eq!(map.size(), len);
+        assert_eq!(map.rank1(..len), count1);
+        assert_eq!(vec.len(), compacts::bits::blocks_by(len, 64));
+        assert_eq!(vec.count1(), count1);
+        assert_eq!(arr.rank1(..len), count1);
+        assert_eq!(pop.rank1(..len), count1);
+        assert_eq!(ewah.size(), len);
+        assert_eq!(ewah.count1(), count1);
+    }
+
+    #[test]
+    fn terminals() {
+        macro_rules! terminals {
+            ($fn:ident) => {{
+                let map = $fn(&*M0, &*M1).materialize::<BitMap<[u64; 512]>>();
+                assert_eq!($fn(&*M0, &*M1).count_ones(), map.count1());
+                assert_eq!($fn(&*M0, &*M1).is_empty(), map.count1() == 0);
+                assert_eq!($fn(&*M0, &*M1).first1(), map.select1(0));
+                assert_eq!(
+                    $fn(&*M0, &*M0).first1(),
+                    $fn(&*M0, &*M0).materialize::<Vec<u64>>().select1(0)
+                );
+
+                let fold = Fold::$fn(vec![&*M0, &*M1, &*M2]).materialize::<BitMap<[u64; 512]>>();
+                assert_eq!(
+                    Fold::$fn(vec![&*M0, &*M1, &*M2]).count_ones(),
+                    fold.count1()
+                );
+                assert_eq!(
+                    Fold::$fn(vec![&*M0, &*M1, &*M2]).is_empty(),
+                    fold.count1() == 0
+                );
+                assert_eq!(Fold::$fn(vec![&*M0, &*M1, &*M2]).first1(), fold.select1(0));
+            }};
+        }
+        terminals!(and);
+        terminals!(or);
+        terminals!(xor);
+        terminals!(and_not);
+
+        for k in 1..=3 {
+            let fold = Fold::threshold(k, vec![&*M0, &*M1, &*M2]).materialize::<Vec<u64>>();
+            let count1 = Fold::threshold(k, vec![&*M0, &*M1, &*M2]).count_ones();
+            assert_eq!(count1, fold.count1());
+            let first1 = Fold::threshold(k, vec![&*M0, &*M1, &*M2]).first1();
+            assert_eq!(first1, fold.select1(0));
+        }
+    }
+
     #[test]
     fn fold_xor() {
         let vec1 = Fold::xor(vec![&*M0, &*M1, &*M2]).collect::<Vec<_>>();
@@ -147,3 +330,667 @@ mod mask {
         assert_eq!(vec1, vec2);
     }
 }
+
+mod map {
+    use super::*;
+
+    #[test]
+    fn optimize() {
+        let mut map = M0.clone();
+        for i in 0..65536 {
+            map.put1(i);
+        }
+        for i in 65536 * 3..65536 * 4 {
+            map.put0(i);
+        }
+
+        let steps = map
+            .into_steps()
+            .filter(|(_, block)| block.any())
+            .collect::<Vec<_>>();
+        let mut optimized = map.clone();
+        assert!(optimized.optimize() > 0);
+        assert_eq!(optimized, map);
+        assert_eq!(optimized.into_steps().collect::<Vec<_>>(), steps);
+        assert_eq!(optimized.count1(), map.count1());
+        assert_eq!(optimized.rank1(..65536 * 5), map.rank1(..65536 * 5));
+    }
+
+    #[test]
+    fn full_steps() {
+        use std::borrow::Cow;
+        let mut full = BitMap::<[u64; 512]>::none(BOUND);
+        for i in 0..1 << 15 {
+            full.put1(i); // block 0 is full
+        }
+        full.optimize();
+        let full_block = vec![u64::MAX; 512];
+        assert_eq!(
+            full.into_steps().next(),
+            Some((0, Cow::Borrowed(&full_block[..])))
+        );
+
+        // a `Full` that union brings from another map is yielded as an all-ones block.
+        let mut map = BitMap::<[u64; 512]>::none(BOUND);
+        map.put1(1 << 20);
+        map.union(&full);
+        let steps = map.into_steps().collect::<Vec<_>>();
+        assert_eq!(steps[0], (0, Cow::Borrowed(&full_block[..])));
+        assert_eq!(map.into_steps().count_ones(), map.count1());
+        assert_eq!(map.and(&full).count_ones(), 1 << 15);
+    }
+}
+
+mod set_ops {
+    use super::*;
+
+    macro_rules! in_place {
+        ($method:ident, $mask:ident) => {{
+            let mut map = M0.clone();
+            map.$method(&*M1);
+            let lhs = map
+                .into_steps()
+                .filter(|(_, block)| block.any())
+                .collect::<Vec<_>>();
+            let rhs = $mask(&*M0, &*M1)
+                .filter(|(_, block)| block.any())
+                .collect::<Vec<_>>();
+            assert_eq!(lhs, rhs);
+            assert_eq!(map.count1(), lhs.iter().map(|(_, b)| b.count1()).sum());
+
+            let mut vec = V0.clone();
+            vec.$method(&*V1);
+
+            let mut arr = A0.clone();
+            arr.$method(&*A1);
+            assert_eq!(arr, BitArray::from(vec.clone()));
+
+            let mut pop = Pop::<u64>::new(BOUND);
+            for i in (0..BOUND).step_by(3) {
+                pop.put1(i);
+            }
+            let mut bits = compacts::bits::sized::<u64>(BOUND);
+            for i in (0..BOUND).step_by(3) {
+                bits.put1(i);
+            }
+            pop.$method(&*V0);
+            bits.$method(&*V0);
+            assert_eq!(pop.count1(), bits.count1());
+            for i in (0..BOUND).step_by(99_991) {
+                assert_eq!(pop.rank1(..i), bits.rank1(..i));
+            }
+        }};
+    }
+
+    #[test]
+    fn intersection() {
+        in_place!(intersection, and);
+    }
+
+    #[test]
+    fn union() {
+        in_place!(union, or);
+    }
+
+    #[test]
+    fn difference() {
+        in_place!(difference, and_not);
+    }
+
+    #[test]
+    fn symmetric_difference() {
+        in_place!(symmetric_difference, xor);
+    }
+}
+
+mod expr {
+    use super::*;
+    use compacts::bits::expr::{Error, Expr};
+    use std::collections::HashMap;
+
+    #[test]
+    fn eval() {
+        let mut map = HashMap::new();
+        map.insert("m0", M0.clone());
+        map.insert("m1", M1.clone());
+        map.insert("m2", M2.clone());
+
+        let eval = |s: &str| {
+            let expr = s.parse::<Expr>().unwrap();
+            expr.eval(&map, BOUND).unwrap().materialize::<Vec<u64>>()
+        };
+
+        let vec = M0.and_not(&*M1).materialize::<Vec<u64>>();
+        assert_eq!(eval("m0 AND NOT m1"), vec);
+        assert_eq!(eval("NOT m1 AND m0"), vec);
+
+        let vec = M0.and(&*M1).and(&*M2).materialize::<Vec<u64>>();
+        assert_eq!(eval("m2 AND (m0 AND m1)"), vec);
+        assert_eq!(eval("THRESHOLD(3, m0, m1, m2)"), vec);
+
+        let vec = M0.xor(&*M1).or(&*M2).materialize::<Vec<u64>>();
+        assert_eq!(eval("m0 XOR m1 OR m2"), vec);
+
+        let vec = M0
+            .or(&*M1)
+            .not::<[u64; 512]>(BOUND)
+            .materialize::<Vec<u64>>();
+        assert_eq!(eval("NOT m0 AND NOT m1"), vec);
+        assert_eq!(eval("NOT (m0 OR m1)"), vec);
+
+        let expr = "m0 AND m3".parse::<Expr>().unwrap();
+        assert_eq!(
+            expr.eval(&map, BOUND).err(),
+            Some(Error::Unknown("m3".to_string()))
+        );
+
+        let names = vec![Expr::Leaf("m0".to_string()), Expr::Leaf("m1".to_string())];
+        let expr = Expr::Threshold(0, names.clone());
+        assert_eq!(expr.eval(&map, BOUND).err(), Some(Error::ZeroThreshold));
+        assert_eq!(
+            Expr::Threshold(3, names)
+                .eval(&map, BOUND)
+                .unwrap()
+                .count_ones(),
+            0
+        );
+    }
+
+    #[test]
+    fn eval_closure() {
+        let source = |name: &str| match name {
+            "m0" => Some(&*M0),
+            "m1" => Some(&*M1),
+            "m2" => Some(&*M2),
+            _ => None,
+        };
+        let eval = |s: &str| {
+            let expr = s.parse::<Expr>().unwrap();
+            expr.eval::<[u64; 512], _>(&source, BOUND)
+                .unwrap()
+                .materialize::<Vec<u64>>()
+        };
+
+        let vec = M0.and(&*M2).and_not(&*M1).materialize::<Vec<u64>>();
+        assert_eq!(eval("m0 AND NOT m1 AND m2"), vec);
+        assert_eq!(eval("NOT NOT m0 AND m2 AND NOT m1"), vec);
+
+        let expr = "m0 OR m3".parse::<Expr>().unwrap();
+        assert_eq!(
+            expr.eval::<[u64; 512], _>(&source, BOUND).err(),
+            Some(Error::Unknown("m3".to_string()))
+        );
+    }
+}
+
+mod index {
+    use super::*;
+    use compacts::bits::index::BitmapIndex;
+
+    #[test]
+    fn eq_in_set_not_eq() {
+        let mut rng = thread_rng();
+        let mut column = (0..200_000)
+            .map(|_| rng.gen_range(0, 10))
+            .map(Some)
+            .collect::<Vec<Option<u32>>>();
+        let mut index = column
+            .iter()
+            .map(|k| k.unwrap())
+            .collect::<BitmapIndex<u32, [u64; 1024]>>();
+
+        for _ in 0..1000 {
+            let row = rng.gen_range(0, column.len());
+            if rng.gen() {
+                assert_eq!(index.remove(row), column[row].is_some());
+                column[row] = None;
+            } else {
+                let key = rng.gen_range(0, 12);
+                index.insert(row, key);
+                column[row] = Some(key);
+            }
+        }
+
+        let check = |index: &BitmapIndex<u32, [u64; 1024]>, column: &[Option<u32>]| {
+            let count =
+                |f: &dyn Fn(u32) -> bool| column.iter().flatten().filter(|&&k| f(k)).count();
+            for key in 0..12 {
+                assert_eq!(index.eq(&key).count_ones(), count(&|k| k == key));
+                assert_eq!(index.not_eq(&key).count_ones(), count(&|k| k != key));
+            }
+            assert_eq!(
+                index.in_set(&[1, 3, 11]).count_ones(),
+                count(&|k| k == 1 || k == 3 || k == 11)
+            );
+
+            let eq = index.eq(&3).materialize::<Vec<u64>>();
+            for (row, key) in column.iter().enumerate().step_by(97) {
+                assert_eq!(row < eq.size() && eq.bit(row), *key == Some(3));
+            }
+        };
+
+        check(&index, &column);
+        index.optimize();
+        check(&index, &column);
+        index.insert(0, 3);
+        column[0] = Some(3);
+        check(&index, &column);
+
+        // a sparse key is kept as `BitMap`, and dropped when its rows are removed.
+        let keys = index.keys();
+        index.insert(5, 20);
+        index.insert(150_000, 20);
+        column[5] = Some(20);
+        column[150_000] = Some(20);
+        index.optimize();
+        assert_eq!(index.keys(), keys + 1);
+        assert_eq!(index.eq(&20).count_ones(), 2);
+        assert_eq!(index.eq(&20).first1(), Some(5));
+        assert!(index.remove(5));
+        assert!(index.remove(150_000));
+        column[5] = None;
+        column[150_000] = None;
+        assert_eq!(index.keys(), keys);
+        assert!(index.eq(&20).is_empty());
+        check(&index, &column);
+
+        // the slot of the dropped key is reused by a new key.
+        index.insert(7, 21);
+        column[7] = Some(21);
+        assert_eq!(index.keys(), keys + 1);
+        assert!(index.eq(&20).is_empty());
+        assert_eq!(index.eq(&21).first1(), Some(7));
+        check(&index, &column);
+    }
+}
+
+mod bsi {
+    use super::*;
+    use compacts::bits::bsi::Bsi;
+
+    macro_rules! compare {
+        ($V:ty, $bound:expr) => {{
+            let mut rng = thread_rng();
+            let column = (0..100_000)
+                .map(|_| {
+                    if rng.gen_bool(0.1) {
+                        None
+                    } else {
+                        Some(rng.gen_range(0, $bound) as $V)
+                    }
+                })
+                .collect::<Vec<Option<$V>>>();
+            let bsi = column.iter().cloned().collect::<Bsi<$V, [u64; 512]>>();
+            let count = |f: &dyn Fn($V) -> bool| column.iter().flatten().filter(|&&v| f(v)).count();
+
+            for row in (0..column.len()).step_by(101) {
+                assert_eq!(bsi.get(row), column[row]);
+            }
+            for _ in 0..5 {
+                let c = rng.gen_range(0, $bound) as $V;
+                let d = rng.gen_range(0, $bound) as $V;
+                assert_eq!(bsi.eq(c).count1(), count(&|v| v == c));
+                assert_eq!(bsi.ne(c).count1(), count(&|v| v != c));
+                assert_eq!(bsi.lt(c).count1(), count(&|v| v < c));
+                assert_eq!(bsi.le(c).count1(), count(&|v| v <= c));
+                assert_eq!(bsi.gt(c).count1(), count(&|v| v > c));
+                assert_eq!(bsi.ge(c).count1(), count(&|v| v >= c));
+                assert_eq!(bsi.range(c..d).count1(), count(&|v| c <= v && v < d));
+                assert_eq!(bsi.range(c..=d).count1(), count(&|v| c <= v && v <= d));
+
+                let lt = bsi.lt(c);
+                let sum = column
+                    .iter()
+                    .flatten()
+                    .filter(|&&v| v < c)
+                    .map(|&v| v as u128)
+                    .sum::<u128>();
+                assert_eq!(bsi.sum(&lt), sum);
+            }
+
+            let mut sorted = column.iter().flatten().cloned().collect::<Vec<_>>();
+            sorted.sort_unstable_by(|a, b| b.cmp(a));
+            for &k in &[0, 1, 10, 1000, sorted.len(), sorted.len() + 1] {
+                let top = bsi.top_k(k, bsi.rows());
+                assert_eq!(top.count1(), std::cmp::min(k, sorted.len()));
+                let sum = sorted.iter().take(k).map(|&v| v as u128).sum::<u128>();
+                assert_eq!(bsi.sum(&top), sum);
+            }
+        }};
+    }
+
+    #[test]
+    fn sized_by_rows() {
+        let len = 200_000;
+        let bsi = (0..len)
+            .map(|row| if row < 10 { Some(5u32) } else { None })
+            .collect::<Bsi<u32, [u64; 1024]>>();
+        let found = vec![
+            bsi.eq(5),
+            bsi.ne(5),
+            bsi.lt(5),
+            bsi.le(5),
+            bsi.gt(4),
+            bsi.ge(5),
+            bsi.range(5..6),
+            bsi.top_k(3, bsi.rows()),
+        ];
+        for map in &found {
+            assert_eq!(map.size(), len);
+            assert!(!map.bit(150_000));
+        }
+        assert_eq!(found[0].count1(), 10);
+        assert_eq!(found[7].count1(), 3);
+        assert_eq!(bsi.sum(bsi.rows().within(5..)), 25);
+    }
+
+    #[test]
+    fn compare_u8() {
+        compare!(u8, 256);
+    }
+
+    #[test]
+    fn compare_u32() {
+        compare!(u32, 1_000_000);
+    }
+
+    #[test]
+    fn compare_u128() {
+        compare!(u128, 1_000_000_000u64);
+    }
+}
+
+mod ewah {
+    use super::*;
+    use compacts::bits::ewah::Ewah;
+
+    #[test]
+    fn bits() {
+        let ewah = Ewah::<u64>::from(&*V0);
+        assert_eq!(ewah.size(), V0.size());
+        assert_eq!(ewah.count1(), V0.count1());
+
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            let i = rng.gen_range(0, V0.size());
+            assert_eq!(ewah.bit(i), V0.bit(i));
+            assert_eq!(ewah.rank1(..i), V0.rank1(..i));
+            let n = rng.gen_range(0, V0.count1());
+            assert_eq!(ewah.select1(n), V0.select1(n));
+        }
+    }
+
+    #[test]
+    fn runs() {
+        // long runs overflow the fields of `u8` markers.
+        let mut rng = thread_rng();
+        let mut bits = Vec::new();
+        let mut pos = 0;
+        for _ in 0..100 {
+            pos += rng.gen_range(0, 5000);
+            let len = rng.gen_range(1, 5000);
+            bits.extend(pos..pos + len);
+            pos += len;
+        }
+        let small = bits.iter().cloned().collect::<Ewah<u8>>();
+        let large = bits.iter().cloned().collect::<Ewah<u64>>();
+        assert_eq!(small.count1(), bits.len());
+        assert_eq!(large.count1(), bits.len());
+        for _ in 0..100 {
+            let n = rng.gen_range(0, bits.len());
+            assert_eq!(small.select1(n), Some(bits[n]));
+            assert_eq!(large.select1(n), Some(bits[n]));
+        }
+        assert!(large.compressed_len() < 1000);
+    }
+
+    #[test]
+    fn ops() {
+        let e0 = M0.materialize::<Ewah<u64>>();
+        let e1 = M1.materialize::<Ewah<u64>>();
+        macro_rules! check {
+            ($fn:ident) => {{
+                let trim = |mut vec: Vec<u64>| {
+                    while vec.last() == Some(&0) {
+                        vec.pop();
+                    }
+                    vec
+                };
+                let ewah = e0.$fn(&e1).steps::<[u64; 512]>().materialize::<Vec<u64>>();
+                let vec = M0.$fn(&*M1).materialize::<Vec<u64>>();
+                assert_eq!(ewah.count1(), vec.count1());
+                assert_eq!(trim(ewah), trim(vec));
+            }};
+        }
+        check!(and);
+        check!(or);
+        check!(xor);
+        check!(and_not);
+    }
+}
+
+mod run_vec {
+    use super::*;
+    use compacts::bits::RunVec;
+    use std::ops::Range;
+
+    fn random_runs(rng: &mut ThreadRng, len: usize) -> Vec<Range<usize>> {
+        (0..200)
+            .map(|_| {
+                let i = rng.gen_range(0, len - 100);
+                i..i + rng.gen_range(1, 100)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bits() {
+        let mut rng = thread_rng();
+        let len = 100_000;
+        let mut runs = RunVec::none(len);
+        let mut vec = vec![0u64; compacts::bits::blocks_by(len, 64)];
+        for range in random_runs(&mut rng, len) {
+            if rng.gen() {
+                runs.insert_range(range.clone());
+                range.for_each(|i| vec.put1(i));
+            } else {
+                runs.remove_range(range.clone());
+                range.for_each(|i| vec.put0(i));
+            }
+        }
+        assert_eq!(runs.count1(), vec.count1());
+        for _ in 0..1000 {
+            let i = rng.gen_range(0, len);
+            assert_eq!(runs.bit(i), vec.bit(i));
+            assert_eq!(runs.rank1(..i), vec.rank1(..i));
+            assert_eq!(runs.select0(i / 2), vec.select0(i / 2));
+            if runs.count1() > 0 {
+                let n = rng.gen_range(0, runs.count1());
+                assert_eq!(runs.select1(n), vec.select1(n));
+            }
+        }
+
+        let ones = runs.runs().map(|r| r.len()).sum::<usize>();
+        let zeros = runs.gaps().map(|r| r.len()).sum::<usize>();
+        assert_eq!(ones, runs.count1());
+        assert_eq!(ones + zeros, len);
+    }
+
+    #[test]
+    fn set_ops() {
+        let mut rng = thread_rng();
+        let len = 100_000;
+        let r0 = random_runs(&mut rng, len).into_iter().collect::<RunVec>();
+        let r1 = random_runs(&mut rng, len).into_iter().collect::<RunVec>();
+        let to_vec = |runs: &RunVec| {
+            let mut vec = vec![0u64; compacts::bits::blocks_by(len, 64)];
+            runs.runs().flatten().for_each(|i| vec.put1(i));
+            vec
+        };
+        let (v0, v1) = (to_vec(&r0), to_vec(&r1));
+        macro_rules! check {
+            ($fn:ident) => {{
+                let mut runs = r0.clone();
+                runs.$fn(&r1);
+                let mut vec = v0.clone();
+                vec.$fn(&v1);
+                assert_eq!(to_vec(&runs), vec);
+            }};
+        }
+        check!(intersection);
+        check!(union);
+        check!(difference);
+        check!(symmetric_difference);
+    }
+}
+
+mod hybrid {
+    use super::*;
+    use compacts::bits::Hybrid;
+
+    #[test]
+    fn bits() {
+        // regions of sparse, dense and run-heavy blocks.
+        let mut rng = thread_rng();
+        let len = 1 << 20;
+        let mut vec = vec![0u64; compacts::bits::blocks_by(len, 64)];
+        for region in (0..len).step_by(1 << 16) {
+            match rng.gen_range(0, 3) {
+                0 => (0..50).for_each(|_| vec.put1(region + rng.gen_range(0, 1 << 16))),
+                1 => (0..30000).for_each(|_| vec.put1(region + rng.gen_range(0, 1 << 16))),
+                _ => {
+                    let mut i = region;
+                    while i < region + (1 << 16) {
+                        let end = std::cmp::min(i + rng.gen_range(1, 2000), region + (1 << 16));
+                        (i..end).for_each(|j| vec.put1(j));
+                        i = end + rng.gen_range(1, 2000);
+                    }
+                }
+            }
+        }
+
+        let hybrid = Hybrid::from(vec.clone());
+        assert_eq!(hybrid.size(), vec.size());
+        assert_eq!(hybrid.count1(), vec.count1());
+        for _ in 0..1000 {
+            let i = rng.gen_range(0, len);
+            assert_eq!(hybrid.bit(i), vec.bit(i));
+            assert_eq!(hybrid.rank1(..i), vec.rank1(..i));
+            let n = rng.gen_range(0, vec.count1());
+            assert_eq!(hybrid.select1(n), vec.select1(n));
+            let n = rng.gen_range(0, vec.count0());
+            assert_eq!(hybrid.select0(n), vec.select0(n));
+        }
+    }
+}
+
+mod persist {
+    use super::*;
+    use compacts::bits::{persist::Persist, Hybrid};
+    use std::io::ErrorKind;
+
+    fn write<P: Persist>(p: &P) -> Vec<u8> {
+        let mut buf = Vec::new();
+        p.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    fn read<P: Persist>(buf: &[u8]) -> Result<P, ErrorKind> {
+        P::read_from(&mut &buf[..]).map_err(|e| e.kind())
+    }
+
+    #[test]
+    fn bit_array() {
+        let buf = write(&*A0);
+        assert_eq!(read::<BitArray<u64>>(&buf).as_ref(), Ok(&*A0));
+
+        // the last select sample is out of its upper block.
+        let mut bad = buf.clone();
+        let n = bad.len();
+        bad[n - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read::<BitArray<u64>>(&bad), Err(ErrorKind::InvalidData));
+
+        // a rank sample of the second super block, its L2 is off by one.
+        let words = A0.as_ref().len();
+        let l0s = compacts::bits::blocks_by(A0.size(), 1 << 32);
+        let at = 8 + 8 + 8 * words + 8 + 8 * l0s + 8 + 8 + 4;
+        let mut bad = buf.clone();
+        bad[at] ^= 1;
+        assert_eq!(read::<BitArray<u64>>(&bad), Err(ErrorKind::InvalidData));
+
+        // `ones` doesn't match the bits.
+        let mut bad = buf;
+        bad[0] ^= 1;
+        assert_eq!(read::<BitArray<u64>>(&bad), Err(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn hybrid() {
+        let hybrid = Hybrid::from(V0.clone());
+        let buf = write(&hybrid);
+        assert_eq!(read::<Hybrid<u64>>(&buf).as_ref(), Ok(&hybrid));
+
+        // len, ranks and selects, the first select points to the last block.
+        let ranks = compacts::bits::blocks_by(V0.size(), 4096) + 1;
+        let at = 8 + 8 + 8 * ranks + 8;
+        let mut bad = buf;
+        bad[at..at + 8].copy_from_slice(&((ranks - 2) as u64).to_le_bytes());
+        assert_eq!(read::<Hybrid<u64>>(&bad), Err(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn bit_map() {
+        let mut map = M0.clone();
+        for i in 0..1 << 16 {
+            map.put1(i); // a full block
+        }
+        let buf = write(&map);
+        let read_map = read::<BitMap<[u64; 512]>>(&buf).unwrap();
+        assert_eq!(read_map, map);
+        assert_eq!(read_map.size(), map.size());
+        assert_eq!(read_map.rank1(..BOUND / 2), map.rank1(..BOUND / 2));
+
+        // the index of the first block is out of the size.
+        let mut bad = buf;
+        bad[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            read::<BitMap<[u64; 512]>>(&bad).err(),
+            Some(ErrorKind::InvalidData)
+        );
+    }
+}
+
+mod veb {
+    use super::*;
+    use compacts::bits::VebSet;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn successor() {
+        let mut rng = thread_rng();
+        let len = 1 << 24;
+        let mut set = VebSet::none(len);
+        let mut btree = BTreeSet::new();
+        for _ in 0..10000 {
+            let i = rng.gen_range(0, len);
+            if rng.gen_range(0, 4) == 0 {
+                assert_eq!(set.remove(i), btree.remove(&i));
+            } else {
+                assert_eq!(set.insert(i), btree.insert(i));
+            }
+        }
+        assert_eq!(set.count1(), btree.len());
+        assert_eq!(set.min(), btree.iter().next().cloned());
+        assert_eq!(set.max(), btree.iter().next_back().cloned());
+        for _ in 0..1000 {
+            let i = rng.gen_range(0, len);
+            assert_eq!(set.next1(i), btree.range(i..).next().cloned());
+            assert_eq!(set.prev1(i), btree.range(..=i).next_back().cloned());
+        }
+        while let Some(min) = set.pop_min() {
+            assert_eq!(Some(min), btree.iter().next().cloned());
+            btree.remove(&min);
+        }
+        assert!(btree.is_empty());
+    }
+}
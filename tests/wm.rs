@@ -1,8 +1,9 @@
 #[allow(unused_imports)]
 use {
     compacts::{
-        bits::{and, or, xor, Fold, Mask},
+        bits::{and, or, xor, Fold, Hybrid, Mask},
         ops::*,
+        text::wavelet_matrix::Mapped,
         BitArray, BitMap, WaveletMatrix,
     },
     lazy_static::lazy_static,
@@ -10,10 +11,212 @@ use {
     rand::prelude::*,
 };
 
+use std::ops::Range;
+
+// A range of `0..=len`, quickcheck generates `i` and `j` in any order.
+fn window(len: usize, i: usize, j: usize) -> Range<usize> {
+    let (i, j) = (i % (len + 1), j % (len + 1));
+    i.min(j)..i.max(j)
+}
+
+// quickcheck generates integers less than 100, these spread them over the whole range,
+// so that `T::MAX` and the top levels of a matrix are also exercised.
+fn wide8(v: u8) -> u8 {
+    if v >= 90 {
+        u8::MAX - (v - 90)
+    } else {
+        v.wrapping_mul(3)
+    }
+}
+
+fn wide16(v: u16) -> u16 {
+    if v >= 90 {
+        u16::MAX - (v - 90)
+    } else {
+        v.wrapping_mul(727)
+    }
+}
+
 quickcheck! {
     fn index_all(vec: Vec<u64>) -> bool {
-        let mut xs = vec.clone();
-        let wm = WaveletMatrix::<u64, BitArray<u64>>::from(&mut xs[..]);
+        let wm = WaveletMatrix::<u64, BitArray<u64>>::from_slice(&vec);
         vec.iter().enumerate().all(|(i, v)| wm.get(i).unwrap() == *v)
     }
+
+    fn hybrid_levels(vec: Vec<u8>) -> bool {
+        let wm0 = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+        let wm1 = vec.iter().cloned().collect::<WaveletMatrix<u8, Hybrid<u64>>>();
+        (0..vec.len()).all(|i| wm0.get(i) == wm1.get(i))
+            && vec.iter().all(|v| {
+                (0..vec.len()).all(|i| wm0.rank(v, ..i) == wm1.rank(v, ..i))
+                    && (0..wm0.count(v)).all(|n| wm0.select(v, n) == wm1.select(v, n))
+            })
+    }
+
+    fn persist(vec: Vec<u16>) -> bool {
+        fn roundtrip<B: Bits + compacts::bits::persist::Persist>(wm: &WaveletMatrix<u16, B>) -> WaveletMatrix<u16, B> {
+            let mut buf = Vec::new();
+            wm.write_to(&mut buf).unwrap();
+            WaveletMatrix::read_from(&mut buf.as_slice()).unwrap()
+        }
+        let wm0 = WaveletMatrix::<u16, BitArray<u64>>::from_slice(&vec);
+        let wm1 = WaveletMatrix::<u16, Hybrid<u32>>::from_slice(&vec);
+        wm0 == roundtrip(&wm0) && wm1 == roundtrip(&wm1)
+    }
+
+    fn range_freq(vec: Vec<u8>, i: usize, j: usize, a: u8, b: u8) -> bool {
+        let vec = vec.into_iter().map(wide8).collect::<Vec<_>>();
+        let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+        let Range { start: i, end: j } = window(vec.len(), i, j);
+        let (a, b) = (wide8(a), wide8(b));
+        let (a, b) = (a.min(b), a.max(b));
+        let view = wm.view(i..j);
+        let naive = |lo: u8, hi: u8| vec[i..j].iter().filter(|&&v| lo <= v && v < hi).count();
+        view.range_freq(a..b) == naive(a, b)
+            && view.range_freq(a..) == vec[i..j].iter().filter(|&&v| a <= v).count()
+            && view.range_freqs(&[0, a, b]) == vec![naive(0, a), naive(a, b)]
+    }
+
+    fn next_prev_value(vec: Vec<u8>, i: usize, j: usize, x: u8) -> bool {
+        let vec = vec.into_iter().map(wide8).collect::<Vec<_>>();
+        let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+        let Range { start: i, end: j } = window(vec.len(), i, j);
+        let x = wide8(x);
+        let view = wm.view(i..j);
+        let next = vec[i..j].iter().filter(|&&v| v >= x).min().cloned();
+        let prev = vec[i..j].iter().filter(|&&v| v < x).max().cloned();
+        view.next_value(&x) == next && view.prev_value(&x) == prev
+    }
+
+    fn report(vec: Vec<u8>, i: usize, j: usize, a: u8, b: u8) -> bool {
+        let vec = vec.into_iter().map(wide8).collect::<Vec<_>>();
+        let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+        let Range { start: i, end: j } = window(vec.len(), i, j);
+        let (a, b) = (wide8(a), wide8(b));
+        let (a, b) = (a.min(b), a.max(b));
+        let view = wm.view(i..j);
+        let naive = vec[i..j]
+            .iter()
+            .enumerate()
+            .filter(|&(_, &v)| a <= v && v < b)
+            .map(|(p, &v)| (p, v))
+            .collect::<Vec<_>>();
+        let mut by_value = naive.clone();
+        by_value.sort_by_key(|&(p, v)| (v, p));
+        let mut by_value_desc = naive.clone();
+        by_value_desc.sort_by_key(|&(p, v)| (std::cmp::Reverse(v), p));
+        view.report(a..b).collect::<Vec<_>>() == by_value
+            && view.report_desc(a..b).collect::<Vec<_>>() == by_value_desc
+            && view.report_by_position(a..b).collect::<Vec<_>>() == naive
+    }
+
+    fn positions(vec: Vec<u8>, i: usize, j: usize, x: u8) -> bool {
+        let vec = vec.into_iter().map(wide8).collect::<Vec<_>>();
+        let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+        let Range { start: i, end: j } = window(vec.len(), i, j);
+        let x = wide8(x);
+        let view = wm.view(i..j);
+        let naive = (0..j - i).filter(|&p| vec[i + p] == x).collect::<Vec<_>>();
+        let mut rev = view.positions(&x).rev().collect::<Vec<_>>();
+        rev.reverse();
+        view.positions(&x).collect::<Vec<_>>() == naive && rev == naive
+    }
+
+    fn intersect(vec: Vec<u8>, i1: usize, j1: usize, i2: usize, j2: usize) -> bool {
+        let vec = vec.into_iter().map(wide8).collect::<Vec<_>>();
+        let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+        let (r1, r2) = (window(vec.len(), i1, j1), window(vec.len(), i2, j2));
+        let count = |r: &Range<usize>, x: u8| vec[r.clone()].iter().filter(|&&v| v == x).count();
+        let naive = (0..=255u8)
+            .map(|x| (x, count(&r1, x), count(&r2, x)))
+            .filter(|&(_, c1, c2)| c1 > 0 && c2 > 0)
+            .collect::<Vec<_>>();
+        wm.view(r1.clone()).intersect(&wm.view(r2.clone())).collect::<Vec<_>>() == naive
+    }
+
+    fn sums(vec: Vec<u16>, i: usize, j: usize, a: u16, b: u16, k: usize) -> bool {
+        let vec = vec.into_iter().map(wide16).collect::<Vec<_>>();
+        let wm = WaveletMatrix::<u16, BitArray<u64>>::from_slice(&vec);
+        let sums = wm.sums();
+        let Range { start: i, end: j } = window(vec.len(), i, j);
+        let (a, b) = (wide16(a), wide16(b));
+        let (a, b) = (a.min(b), a.max(b));
+        let naive = |lo: u16, hi: u16| {
+            vec[i..j].iter().filter(|&&v| lo <= v && v < hi).map(|&v| u128::from(v)).sum::<u128>()
+        };
+        let mut sorted = vec[i..j].to_vec();
+        sorted.sort();
+        let smallest = sorted.iter().take(k).map(|&v| u128::from(v)).sum::<u128>();
+        sums.range_sum(i..j, a..b) == naive(a, b)
+            && sums.range_sum(i..j, a..=b) == naive(a, b) + u128::from(b) * vec[i..j].iter().filter(|&&v| v == b).count() as u128
+            && sums.range_sum(i..j, ..) == naive(0, u16::MAX) + u128::from(u16::MAX) * vec[i..j].iter().filter(|&&v| v == u16::MAX).count() as u128
+            && sums.sum_smallest_k(i..j, k) == smallest
+    }
+
+    fn mapped(vec: Vec<u8>, i: usize, j: usize, x: u8) -> bool {
+        // spreads values over a large domain
+        let spread = |v: u8| u64::from(v) << 40 | u64::from(v);
+        let vec = vec.into_iter().map(|v| spread(wide8(v))).collect::<Vec<_>>();
+        let wm = Mapped::<u64, BitArray<u64>>::from_slice(&vec);
+        let Range { start: i, end: j } = window(vec.len(), i, j);
+        let view = wm.view(i..j);
+        let x = spread(wide8(x));
+
+        let mut sorted = vec[i..j].to_vec();
+        sorted.sort();
+        let naive_min = {
+            let mut min = sorted.iter().filter(|&&v| v >= x).map(|&v| (sorted.iter().filter(|&&w| w == v).count(), v)).collect::<Vec<_>>();
+            min.dedup();
+            min
+        };
+        let positions = (0..j - i).filter(|&p| vec[i + p] == x).collect::<Vec<_>>();
+
+        (1 << wm.depth()) >= wm.values().len()
+            && (0..vec.len()).all(|p| wm.get(p) == Some(vec[p]))
+            && (0..j - i).all(|k| view.quantile(k).map(|(_, v)| v) == Some(sorted[k]))
+            && view.count(&x) == positions.len()
+            && view.counts(&x).lt == sorted.iter().filter(|&&v| v < x).count()
+            && view.counts(&(x + 1)).lt == sorted.iter().filter(|&&v| v <= x).count()
+            && (0..positions.len()).all(|n| view.select(&x, n) == Some(positions[n]))
+            && view.search(x, None).min().collect::<Vec<_>>() == naive_min
+    }
+}
+
+#[test]
+fn report_long() {
+    // skewed values, so that leaves are long and located by chunks from both ends.
+    let mut rng = thread_rng();
+    let vec = (0..20_000)
+        .map(|_| match rng.gen_range(0, 10) {
+            0..=7 => rng.gen_range(0, 3),
+            _ => rng.gen::<u8>(),
+        })
+        .collect::<Vec<u8>>();
+    let wm = WaveletMatrix::<u8, BitArray<u64>>::from_slice(&vec);
+    let (i, j) = (123, 19_000);
+    let view = wm.view(i..j);
+
+    for x in 0..5 {
+        let naive = (0..j - i).filter(|&p| vec[i + p] == x).collect::<Vec<_>>();
+        let mut positions = view.positions(&x);
+        assert_eq!(positions.len(), naive.len());
+        let (mut front, mut back) = (Vec::new(), Vec::new());
+        while let Some(p) = positions.next() {
+            front.push(p);
+            back.extend(positions.next_back());
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, naive);
+    }
+
+    let naive = (0..j - i)
+        .filter(|&p| 1 <= vec[i + p] && vec[i + p] < 200)
+        .map(|p| (p, vec[i + p]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        view.report_by_position(1..200).take(10).collect::<Vec<_>>(),
+        naive[..10]
+    );
+    assert_eq!(view.report_by_position(1..200).collect::<Vec<_>>(), naive);
 }